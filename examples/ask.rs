@@ -49,7 +49,7 @@ fn main() {
     actor_system.spawn_threads(1);
 
     let props = Props::new(Arc::new(Answerer::new), ());
-    let answerer = actor_system.actor_of(props, "answerer".to_owned());
+    let answerer = actor_system.actor_of(props, "answerer".to_owned()).unwrap();
 
     // FIXME(gamazeps): eventual futures seem to be a bad idea, as we have to await with them.
     // And that kinda beats the whole point of having futures.
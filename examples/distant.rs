@@ -29,7 +29,7 @@ fn main() {
     let actor_system = ActorSystem::new("test".to_owned());
 
     let props = Props::new(Arc::new(Dummy::new),());
-    let _local_actor = actor_system.actor_of(props.clone(), "dummy".to_owned());
+    let _local_actor = actor_system.actor_of(props.clone(), "dummy".to_owned()).unwrap();
 
     std::thread::sleep(Duration::from_millis(10));
     actor_system.shutdown();
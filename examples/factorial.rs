@@ -36,9 +36,10 @@ fn main() {
     actor_system.spawn_threads(2);
 
     let props_factorial = Props::new(Arc::new(Factorial::new), ());
-    let factorial_actor_ref_1 = actor_system.actor_of(props_factorial.clone(), "sender".to_owned());
+    let factorial_actor_ref_1 = actor_system.actor_of(props_factorial.clone(),
+                                                      "sender".to_owned()).unwrap();
     let factorial_actor_ref_2 = actor_system.actor_of(props_factorial.clone(),
-                                                      "receiver".to_owned());
+                                                      "receiver".to_owned()).unwrap();
 
     factorial_actor_ref_1.tell_to(factorial_actor_ref_2.clone(), (3u32, 1u32));
     factorial_actor_ref_1.tell_to(factorial_actor_ref_2.clone(), (7u32, 1u32));
@@ -24,7 +24,7 @@ struct HelloWorld;
 impl Actor for HelloWorld {
     fn pre_start(&self, context: ActorCell) {
         let props = Props::new(Arc::new(Greeter::new), ());
-        let greeter = context.actor_of(props, "greeter".to_owned());
+        let greeter = context.actor_of(props, "greeter".to_owned()).unwrap();
         context.tell(greeter, Greetings::Greet);
     }
 
@@ -67,7 +67,7 @@ fn main() {
     actor_system.spawn_threads(1);
 
     let props = Props::new(Arc::new(HelloWorld::new), ());
-    let _actor = actor_system.actor_of(props, "hello_world".to_owned());
+    let _actor = actor_system.actor_of(props, "hello_world".to_owned()).unwrap();
 
     std::thread::sleep(Duration::from_millis(10));
     actor_system.shutdown();
@@ -11,7 +11,7 @@ use std::any::Any;
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
-use robots::actors::{Actor, ActorSystem, ActorCell, ActorContext, Props};
+use robots::actors::{Actor, ActorSystem, ActorCell, Props};
 
 #[derive(Copy, Clone)]
 enum InternalStateMessage {
@@ -51,9 +51,10 @@ fn main() {
     actor_system.spawn_threads(2);
 
     let restarted_props = Props::new(Arc::new(InternalState::new), 3);
-    let restarted_actor_ref_1 = actor_system.actor_of(restarted_props.clone(), "sender".to_owned());
+    let restarted_actor_ref_1 = actor_system.actor_of(restarted_props.clone(),
+                                                      "sender".to_owned()).unwrap();
     let restarted_actor_ref_2 = actor_system.actor_of(restarted_props.clone(),
-                                                      "receiver".to_owned());
+                                                      "receiver".to_owned()).unwrap();
 
     restarted_actor_ref_1.tell_to(restarted_actor_ref_2.clone(), InternalStateMessage::Get);
     restarted_actor_ref_1.tell_to(restarted_actor_ref_2.clone(), InternalStateMessage::Set(7));
@@ -0,0 +1,80 @@
+//! Derive macros for the RobotS actor system.
+//!
+//! `#[derive(Dispatch)]` implements `robots::actors::Dispatch` for an enum of messages, so that
+//! the `Box<Any>` given to `Actor::receive` can be converted to the enum and matched
+//! exhaustively.
+
+#![warn(missing_docs)]
+
+#![deny(warnings)]
+
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
+extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use syn::{Data, DeriveInput, Error, Fields};
+
+/// Derives `robots::actors::Dispatch` for an enum of messages.
+///
+/// The enum itself is accepted, and for the single field variants marked with `#[dispatch_from]`
+/// the type of the field is accepted too: such a message is wrapped in the variant.
+#[proc_macro_derive(Dispatch, attributes(dispatch_from))]
+pub fn derive_dispatch(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    match dispatch(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+fn dispatch(input: &DeriveInput) -> Result<TokenStream2, Error> {
+    let name = &input.ident;
+    let data = match input.data {
+        Data::Enum(ref data) => data,
+        _ => return Err(Error::new_spanned(name, "Dispatch can only be derived for enums")),
+    };
+
+    let mut conversions = Vec::new();
+    for variant in &data.variants {
+        if !variant.attrs.iter().any(|attr| attr.path().is_ident("dispatch_from")) {
+            continue;
+        }
+        let field = match variant.fields {
+            Fields::Unnamed(ref fields) if fields.unnamed.len() == 1 => &fields.unnamed[0],
+            _ => {
+                return Err(Error::new_spanned(&variant.ident,
+                                              "#[dispatch_from] needs a variant with a single \
+                                               unnamed field"))
+            }
+        };
+        let variant = &variant.ident;
+        let ty = &field.ty;
+        conversions.push(quote! {
+            let message = match message.downcast::<#ty>() {
+                ::std::result::Result::Ok(message) => {
+                    return ::std::result::Result::Ok(#name::#variant(*message));
+                }
+                ::std::result::Result::Err(message) => message,
+            };
+        });
+    }
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::robots::actors::Dispatch for #name #ty_generics #where_clause {
+            fn dispatch(message: ::std::boxed::Box<dyn std::any::Any>)
+                        -> ::std::result::Result<Self, ::std::boxed::Box<dyn std::any::Any>> {
+                let message = match message.downcast::<Self>() {
+                    ::std::result::Result::Ok(message) => return ::std::result::Result::Ok(*message),
+                    ::std::result::Result::Err(message) => message,
+                };
+                #(#conversions)*
+                ::std::result::Result::Err(message)
+            }
+        }
+    })
+}
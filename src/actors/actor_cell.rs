@@ -3,18 +3,30 @@
 /// It is used to handle messages, system messages, termination, initialization, restarting and
 /// creation of actors.
 
-extern crate eventual;
+extern crate rand;
 
 use std::any::Any;
 use std::collections::VecDeque;
-use std::mem;
-use std::sync::{Arc, Mutex, RwLock, Weak};
-
-use self::eventual::{Async, Future};
-
-use actors::{Actor, ActorPath, ActorRef, ActorSystem, Message};
+use std::fmt;
+#[cfg(feature = "tokio")]
+use std::future::Future as StdFuture;
+use std::mem::size_of;
+use std::sync::{Arc, Mutex, MutexGuard, RwLock, Weak};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use actors::{ActorPath, ActorRef, ActorSnapshot, ActorSystem, AskTimeout, BoxFuture, Cancellable,
+             EventualFuture, Message, MessageReceiver, Props, ReplyTo};
+use actors::ask::{AskActor, AskArgs};
+use actors::async_actor::ResumeWaker;
+use actors::blocking::AskFuture;
 use actors::name_resolver::ResolveRequest;
-use actors::props::ActorFactory;
+use actors::props::{ActorFactory, ActorInstance};
+use actors::reply_to::{AdaptedReply, MessageAdapter};
+#[cfg(feature = "tokio")]
+use actors::tokio_bridge::PipeToActor;
+use actors::watchdog::{inner_message_type_name, register_message_type};
 
 enum Ref<T: ?Sized> {
     StrongRef(Arc<T>),
@@ -39,6 +51,10 @@ macro_rules! unwrap_inner {
 pub struct ActorCell {
     // We have an inner structure in order to be able to generate new ActorCell easily.
     inner_cell: Ref<InnerActorCell>,
+    // These are kept outside of the inner cell so that they are still available once the actor is
+    // stopped (to send dead letters for example).
+    system: ActorSystem,
+    uid: u64,
 }
 
 impl Clone for ActorCell {
@@ -48,6 +64,8 @@ impl Clone for ActorCell {
                 Ref::StrongRef(ref inner) => Arc::downgrade(&inner),
                 Ref::WeakRef(ref inner) => inner.clone(),
             }),
+            system: self.system.clone(),
+            uid: self.uid,
         }
     }
 }
@@ -60,32 +78,73 @@ impl ActorCell {
                father: ActorRef,
                path: Arc<ActorPath>)
                -> ActorCell {
+        let uid = new_uid();
         ActorCell {
             inner_cell: Ref::StrongRef(Arc::new(InnerActorCell::new(props,
-                                                                    system,
+                                                                    system.clone(),
                                                                     father,
-                                                                    path))),
+                                                                    path,
+                                                                    uid))),
+            system: system,
+            uid: uid,
         }
     }
 
+    /// Unique identifier of this incarnation of the actor.
+    ///
+    /// An actor that is stopped and then created again at the same path gets a new uid, whereas a
+    /// restarted actor keeps its uid.
+    pub fn uid(&self) -> u64 {
+        self.uid
+    }
+
+    /// Gives the ActorSystem the actor belongs to.
+    pub fn system(&self) -> ActorSystem {
+        self.system.clone()
+    }
+
     /// Puts a message with its sender in the Actor's mailbox and schedules the Actor.
-    pub fn receive_message(&self, message: InnerMessage, sender: ActorRef) {
+    ///
+    /// If the actor is no longer alive the message is given back.
+    pub fn receive_message(&self, message: InnerMessage, sender: ActorRef) -> Result<(), InnerMessage> {
         let inner = unwrap_inner!(self.inner_cell, {
-            println!("A message was send to a ref to a stopped actor");
-            return;
+            return Err(message);
         });
         inner.receive_message(message, sender);
         inner.system.enqueue_actor(self.actor_ref());
+        Ok(())
     }
 
     /// Puts a system message with its sender in the Actor's system mailbox and schedules the Actor.
-    pub fn receive_system_message(&self, system_message: SystemMessage) {
+    ///
+    /// If the actor is no longer alive the system message is given back.
+    pub fn receive_system_message(&self, system_message: SystemMessage) -> Result<(), SystemMessage> {
         let inner = unwrap_inner!(self.inner_cell, {
-            println!("A message was send to a ref to a stopped actor");
-            return;
+            return Err(system_message);
         });
         inner.receive_system_message(system_message);
         inner.system.enqueue_actor(self.actor_ref());
+        Ok(())
+    }
+
+    /// Takes a snapshot of the actor and of its descendants, None if the actor is no longer alive.
+    pub fn snapshot(&self) -> Option<ActorSnapshot> {
+        let inner = unwrap_inner!(self.inner_cell, {
+            return None;
+        });
+        Some(inner.snapshot())
+    }
+
+    /// Sender of the last message given to the actor, None before the first one (in `pre_start`
+    /// for example).
+    ///
+    /// Unlike `ActorContext::sender` this does not panic when there is no message.
+    pub fn current_sender(&self) -> Option<ActorRef> {
+        let inner = unwrap_inner!(self.inner_cell, {
+            return None;
+        });
+        let current_sender = inner.current_sender.lock().unwrap();
+        current_sender.clone()
     }
 
     /// Makes the Actor handle an envelope in its mailbox.
@@ -96,6 +155,116 @@ impl ActorCell {
         });
         inner.handle_envelope(self.clone());
     }
+
+    /// Registers `receiver` as a child of this actor with a generated name, its path can then be
+    /// resolved like the one of an actor.
+    ///
+    /// The receiver has no lifecycle, it stays a child until it is removed with a `KillMe`, as
+    /// actors stopping themselves are.
+    pub fn receiver_of_anonymous(&self, receiver: Arc<MessageReceiver>) -> ActorRef {
+        let inner = unwrap_inner!(self.inner_cell, {
+            panic!("Tried to register a receiver in the context of a no longer existing actor");
+        });
+        let name = anonymous_name(inner.anonymous_children.fetch_add(1, Ordering::SeqCst));
+        let path = self.path().child(name);
+        let actor_ref = ActorRef::with_receiver(receiver, path.clone());
+        inner.children.lock().unwrap().push((path, actor_ref.clone()));
+        self.tell(inner.system.name_resolver(), ResolveRequest::Add(actor_ref.clone()));
+        actor_ref
+    }
+
+    /// Creates a child actor with the given name, the name is assumed to be valid.
+    fn spawn_child(&self, props: Arc<ActorFactory>, name: String) -> Result<ActorRef, ActorCreationError> {
+        let inner = unwrap_inner!(self.inner_cell, {
+            panic!("Tried to create an actor from the context of a no longer existing actor");
+        });
+        let path = self.path().child(name);
+        // We keep the lock on the children for the whole creation, otherwise two children with the
+        // same name could be created concurrently.
+        let mut children = inner.children.lock().unwrap();
+        if children.iter().any(|child| child.0 == path) {
+            return Err(ActorCreationError::ActorAlreadyExists(path));
+        }
+        let uid = new_uid();
+        let inner_cell = InnerActorCell::new(props,
+                                             inner.system.clone(),
+                                             self.actor_ref(),
+                                             path.clone(),
+                                             uid);
+        let actor_cell = ActorCell {
+            inner_cell: Ref::StrongRef(Arc::new(inner_cell)),
+            system: inner.system.clone(),
+            uid: uid,
+        };
+        let internal_ref = ActorRef::with_cell(actor_cell, path.clone());
+        let external_ref = internal_ref.clone();
+        children.push((path.clone(), internal_ref));
+        inner.monitoring.lock().unwrap().push(external_ref.clone());
+        external_ref.receive_system_message(SystemMessage::Start);
+        // This is a bit messy, but we have a chicken / egg issue otherwise when creating the name
+        // resolver actor.
+        if *(path.logical_path()) != "/system/name_resolver" {
+            self.tell(inner.system.name_resolver(), ResolveRequest::Add(external_ref.clone()));
+        }
+        Ok(external_ref)
+    }
+}
+
+/// Errors that can happen when creating an actor.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ActorCreationError {
+    /// The given name cannot be used as an actor name.
+    ///
+    /// Names must not be empty, must not contain a `/` and must not start with a `$` (those are
+    /// reserved for anonymous actors).
+    InvalidActorName(String),
+
+    /// An actor with the same path already exists.
+    ActorAlreadyExists(Arc<ActorPath>),
+}
+
+impl fmt::Display for ActorCreationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ActorCreationError::InvalidActorName(ref name) => {
+                write!(f, "invalid actor name: {:?}", name)
+            }
+            ActorCreationError::ActorAlreadyExists(ref path) => {
+                write!(f, "an actor already exists at {}", path.logical_path())
+            }
+        }
+    }
+}
+
+/// Generates a new uid for an actor incarnation or a future.
+///
+/// 0 is never generated, it is reserved for refs to distant actors and to Cthulhu.
+pub fn new_uid() -> u64 {
+    loop {
+        let uid = rand::random::<u64>();
+        if uid != 0 {
+            return uid;
+        }
+    }
+}
+
+fn is_valid_actor_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains('/') && !name.starts_with('$')
+}
+
+/// Gives the name of the n-th anonymous child of an actor: `$a`, `$b`, ..., `$z`, `$ba`, ...
+fn anonymous_name(n: usize) -> String {
+    let alphabet = b"abcdefghijklmnopqrstuvwxyz";
+    let mut digits = Vec::new();
+    // Bijective base 26, so that `$aa` comes right after `$z`.
+    let mut n = n + 1;
+    while n > 0 {
+        n -= 1;
+        digits.push(alphabet[n % 26]);
+        n /= 26;
+    }
+    digits.reverse();
+    format!("${}", String::from_utf8(digits).unwrap())
 }
 
 /// This is the API that Actors are supposed to see of their context while handling a message.
@@ -104,14 +273,55 @@ pub trait ActorContext {
     fn actor_ref(&self) -> ActorRef;
 
     /// Spawns a child actor.
-    fn actor_of(&self, props: Arc<ActorFactory>, name: String) -> ActorRef;
+    ///
+    /// This fails if the name is not a valid actor name or if the actor already has a child with
+    /// that name.
+    fn actor_of(&self, props: Arc<ActorFactory>, name: String) -> Result<ActorRef, ActorCreationError>;
+
+    /// Spawns a child actor with a generated unique name, such as `$a` or `$b`.
+    fn actor_of_anonymous(&self, props: Arc<ActorFactory>) -> ActorRef;
 
     /// Sends a Message to the targeted ActorRef.
     fn tell<MessageTo: Message>(&self, to: ActorRef, message: MessageTo);
 
+    /// Sends a Message to the targeted ActorRef, keeping the sender of the message being handled so
+    /// that `to` replies to it directly.
+    ///
+    /// This is what intermediaries such as routers and proxies use.
+    fn forward<MessageTo: Message>(&self, to: ActorRef, message: MessageTo);
+
+    /// Sends a Message to the targeted ActorRef once `delay` has elapsed.
+    fn schedule_once<MessageTo: Message>(&self,
+                                         delay: Duration,
+                                         to: ActorRef,
+                                         message: MessageTo)
+                                         -> Cancellable;
+
+    /// Sends `message` to `target` without blocking, the reply (or an `AskTimeout` if there is none
+    /// within `timeout`) is converted by `map_reply` and delivered to this actor as a regular
+    /// message.
+    ///
+    /// The request is sent by a temporary actor living under `/temp`, which is also the sender of
+    /// the converted reply.
+    fn ask_with<MessageTo, Reply, F>(&self,
+                                     target: ActorRef,
+                                     message: MessageTo,
+                                     timeout: Duration,
+                                     map_reply: F)
+        where MessageTo: Message,
+              Reply: Message,
+              F: Fn(Result<Box<Any>, AskTimeout>) -> Reply + Send + Sync + 'static;
+
     /// Requests the targeted actor to stop.
     fn stop(&self, actor_ref: ActorRef);
 
+    /// Starts monitoring the targeted actor, `Actor::receive_termination` will be called when it
+    /// is stopped (right away if it is already stopped).
+    fn watch(&self, actor_ref: ActorRef);
+
+    /// Stops monitoring the targeted actor.
+    fn unwatch(&self, actor_ref: ActorRef);
+
     /// Asks the father of the actor to terminate it.
     fn kill_me(&self);
 
@@ -131,11 +341,35 @@ pub trait ActorContext {
     fn path(&self) -> Arc<ActorPath>;
 
     /// Tries to give an address from an actor path.
-    /// Note that eventual futures are lazy, thus you need to await on the Future at some point,
+    /// Note that eventual futures are lazy, thus you need to await on the AskFuture at some point,
     /// this makes this a synchronous call.
     // FIXME(gamazeps): Fix that. This should be fixable by improving on the futures without
     // touching this specific code here.
-    fn identify_actor(&self, _name: String) -> Future<Option<ActorRef>, &'static str>;
+    fn identify_actor(&self, _name: String) -> AskFuture<Option<ActorRef>>;
+
+    /// Gives a `ReplyTo` whose replies are converted with `adapter` and sent to this actor, so that
+    /// it can receive the replies of actors using another message type.
+    ///
+    /// The conversion is done by this actor just before handling the converted reply, which keeps
+    /// the original sender.
+    fn message_adapter<R: Message, M: Message>(&self, adapter: Arc<Fn(R) -> M + Send + Sync>)
+                                               -> ReplyTo<R>;
+
+    /// Same as `identify_actor`, with a `std::future::Future` which can be awaited in async Rust.
+    fn identify_actor_async(&self, name: String) -> EventualFuture<Option<ActorRef>, &'static str>;
+
+    /// Gives the stashed messages back to the actor, they are handled before the other messages
+    /// of the mailbox (see `Actor::stashes`).
+    fn unstash_all(&self);
+
+    /// Spawns `future` as a task on the tokio runtime of the actor system, its output is sent to
+    /// this actor once it is completed, as a message sent by the actor itself.
+    ///
+    /// This panics if the actor system does not run on tokio (see `ActorSystem::with_tokio`).
+    #[cfg(feature = "tokio")]
+    fn spawn_task<F>(&self, future: F)
+        where F: StdFuture + Send + 'static,
+              F::Output: Message;
 }
 
 impl ActorContext for ActorCell {
@@ -143,38 +377,39 @@ impl ActorContext for ActorCell {
         ActorRef::with_cell(self.clone(), self.path())
     }
 
-    fn actor_of(&self, props: Arc<ActorFactory>, name: String) -> ActorRef {
+    fn actor_of(&self, props: Arc<ActorFactory>, name: String) -> Result<ActorRef, ActorCreationError> {
+        if !is_valid_actor_name(&name) {
+            return Err(ActorCreationError::InvalidActorName(name));
+        }
+        self.spawn_child(props, name)
+    }
+
+    fn actor_of_anonymous(&self, props: Arc<ActorFactory>) -> ActorRef {
         let inner = unwrap_inner!(self.inner_cell, {
             panic!("Tried to create an actor from the context of a no longer existing actor");
         });
-        let path = self.path().child(name);
-        let inner_cell = InnerActorCell::new(props,
-                                             inner.system.clone(),
-                                             self.actor_ref(),
-                                             path.clone());
-        let actor_cell = ActorCell { inner_cell: Ref::StrongRef(Arc::new(inner_cell)) };
-        let internal_ref = ActorRef::with_cell(actor_cell, path.clone());
-        let external_ref = internal_ref.clone();
-        inner.children.lock().unwrap().push((path.clone(), internal_ref));
-        inner.monitoring.lock().unwrap().push(external_ref.clone());
-        external_ref.receive_system_message(SystemMessage::Start);
-        // This is a bit messy, but we have a chicken / egg issue otherwise when creating the name
-        // resolver actor.
-        if *(path.logical_path()) != "/system/name_resolver" {
-            self.tell(inner.system.name_resolver(), ResolveRequest::Add(external_ref.clone()));
-        }
-        external_ref
+        // Generated names start with a `$`, which is forbidden in user given names, so they can
+        // not collide with them.
+        let name = anonymous_name(inner.anonymous_children.fetch_add(1, Ordering::SeqCst));
+        self.spawn_child(props, name).unwrap()
     }
 
     fn tell<MessageTo: Message>(&self, to: ActorRef, message: MessageTo) {
-        let path = to.path();
-        match *path {
-            ActorPath::Local(_) => to.receive(InnerMessage::Message(Box::new(message)), self.actor_ref()),
-            ActorPath::Distant(ref path) => {
-                println!("Sent a message of size {} to distant actor {}:{}", mem::size_of::<MessageTo>(),
-                path.distant_logical_path(), path.addr_port());
-            },
-        }
+        send_message(to, message, self.actor_ref());
+    }
+
+    fn forward<MessageTo: Message>(&self, to: ActorRef, message: MessageTo) {
+        send_message(to, message, self.sender());
+    }
+
+    fn schedule_once<MessageTo: Message>(&self,
+                                         delay: Duration,
+                                         to: ActorRef,
+                                         message: MessageTo)
+                                         -> Cancellable {
+        // The sender is computed now, as the actor might be stopped when the message is sent.
+        let sender = self.actor_ref();
+        self.system.scheduler().schedule_once(delay, move || send_message(to, message, sender))
     }
 
     fn sender(&self) -> ActorRef {
@@ -186,11 +421,38 @@ impl ActorContext for ActorCell {
         current_sender.as_ref().unwrap().clone()
     }
 
+    fn ask_with<MessageTo, Reply, F>(&self,
+                                     target: ActorRef,
+                                     message: MessageTo,
+                                     timeout: Duration,
+                                     map_reply: F)
+        where MessageTo: Message,
+              Reply: Message,
+              F: Fn(Result<Box<Any>, AskTimeout>) -> Reply + Send + Sync + 'static
+    {
+        let args = AskArgs {
+            asker: self.actor_ref(),
+            target: target,
+            message: message,
+            timeout: timeout,
+            map_reply: Arc::new(map_reply),
+        };
+        self.system.temp_actor_of(Props::new(Arc::new(AskActor::new), args));
+    }
+
     fn stop(&self, actor_ref: ActorRef) {
         actor_ref.receive(InnerMessage::Control(ControlMessage::PoisonPill),
                           self.actor_ref());
     }
 
+    fn watch(&self, actor_ref: ActorRef) {
+        actor_ref.receive_system_message(SystemMessage::Watch(self.actor_ref()));
+    }
+
+    fn unwatch(&self, actor_ref: ActorRef) {
+        actor_ref.receive_system_message(SystemMessage::Unwatch(self.actor_ref()));
+    }
+
     fn kill_me(&self) {
         self.father().receive(InnerMessage::Control(ControlMessage::KillMe(self.actor_ref())),
                               self.actor_ref());
@@ -230,22 +492,64 @@ impl ActorContext for ActorCell {
         inner.path.clone()
     }
 
-    fn identify_actor(&self, name: String) -> Future<Option<ActorRef>, &'static str> {
+    fn identify_actor(&self, name: String) -> AskFuture<Option<ActorRef>> {
         let inner = unwrap_inner!(self.inner_cell, {
             panic!("Tried to get the actor system of a no longer existing actor while resolving \
                     a path. This should *never* happen");
         });
-        inner.system.name_resolver()
-            .ask(ResolveRequest::Get(name))
-            .and_then(|x| {
-                Ok(*Box::<Any>::downcast::<Option<ActorRef>>(x).unwrap())
-            })
+        inner.system.name_resolver().ask_typed(|reply_to| ResolveRequest::Get(name, reply_to))
+    }
+
+    fn message_adapter<R: Message, M: Message>(&self, adapter: Arc<Fn(R) -> M + Send + Sync>)
+                                               -> ReplyTo<R> {
+        MessageAdapter::reply_to(self.actor_ref(), self.system(), adapter)
+    }
+
+    fn unstash_all(&self) {
+        let inner = unwrap_inner!(self.inner_cell, {
+            panic!("Tried to unstash the messages of a no longer existing actor");
+        });
+        // Each message needs to be scheduled again, as the scheduling of a stashed message was
+        // used when it was stashed.
+        for _ in 0..inner.unstash_all() {
+            inner.system.enqueue_actor(self.actor_ref());
+        }
+    }
+
+    fn identify_actor_async(&self, name: String) -> EventualFuture<Option<ActorRef>, &'static str> {
+        EventualFuture::new(self.identify_actor(name))
+    }
+
+    #[cfg(feature = "tokio")]
+    fn spawn_task<F>(&self, future: F)
+        where F: StdFuture + Send + 'static,
+              F::Output: Message
+    {
+        let handle = self.system
+                         .tokio_handle()
+                         .expect("spawn_task needs an actor system created with `ActorSystem::with_tokio`");
+        let _ = handle.spawn(PipeToActor::new(future, self.actor_ref()));
     }
 }
 
-#[derive(PartialEq)]
-/// Interna representation of the actor's state.
-enum ActorState {
+/// Sends a message to `to` from `sender`, whether it is local or distant.
+pub fn send_message<MessageTo: Message>(to: ActorRef, message: MessageTo, sender: ActorRef) {
+    let path = to.path();
+    match *path {
+        ActorPath::Local(_) => {
+            register_message_type::<MessageTo>();
+            to.receive(InnerMessage::Message(Box::new(message)), sender)
+        }
+        ActorPath::Distant(ref path) => {
+            println!("Sent a message of size {} to distant actor {}:{}", size_of::<MessageTo>(),
+            path.distant_logical_path(), path.addr_port());
+        },
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// State of an actor.
+pub enum ActorState {
     /// The actor has panicked and has not yet been restarded.
     Failed,
     /// The actor is up and running.
@@ -307,12 +611,23 @@ pub enum SystemMessage {
 
     /// Tells an actor that its child failed.
     Failure(ActorRef),
+
+    /// Tells an actor that the given actor monitors it and wants to know when it is terminated.
+    Watch(ActorRef),
+
+    /// Tells an actor that the given actor no longer monitors it.
+    Unwatch(ActorRef),
+
+    /// Tells an actor that the future of its current message was woken and can be polled again.
+    Resume,
 }
 
 /// Structure used to store a message and its sender.
 struct Envelope {
     message: InnerMessage,
     sender: ActorRef,
+    // When the envelope was put in the mailbox, for the metrics.
+    enqueued: Instant,
 }
 
 /// Types of message that can be sent to an actor that will be treated normally.
@@ -340,6 +655,8 @@ pub enum ControlMessage {
 
 struct InnerActorCell {
     mailbox: Mutex<VecDeque<Envelope>>,
+    // Messages put aside by the actor, see `Actor::stashes`.
+    stash: Mutex<VecDeque<Envelope>>,
     system_mailbox: Mutex<VecDeque<SystemMessage>>,
     props: Arc<ActorFactory>,
     system: ActorSystem,
@@ -348,10 +665,22 @@ struct InnerActorCell {
     busy: Mutex<()>,
     father: ActorRef,
     children: Mutex<Vec<(Arc<ActorPath>, ActorRef)>>,
+    anonymous_children: AtomicUsize,
     monitoring: Mutex<Vec<ActorRef>>,
+    // Actors to notify when this actor is terminated.
+    watchers: Mutex<Vec<ActorRef>>,
+    uid: u64,
     actor_state: Arc<RwLock<ActorState>>,
+    // Number of times the actor was restarted after a failure.
+    restarts: AtomicUsize,
+    // Future of the message being handled asynchronously, no other message is handled meanwhile.
+    pending: Mutex<Option<BoxFuture>>,
+    suspended: AtomicBool,
+    // Number of times the actor was scheduled while suspended, it is scheduled again that many
+    // times when the future completes.
+    deferred: AtomicUsize,
     _monitored: Mutex<Vec<ActorRef>>,
-    actor: RwLock<Arc<Actor>>,
+    actor: Mutex<ActorInstance>,
 }
 
 impl InnerActorCell {
@@ -359,11 +688,13 @@ impl InnerActorCell {
     fn new(props: Arc<ActorFactory>,
            system: ActorSystem,
            father: ActorRef,
-           path: Arc<ActorPath>)
+           path: Arc<ActorPath>,
+           uid: u64)
            -> InnerActorCell {
         InnerActorCell {
-            actor: RwLock::new(props.create()),
+            actor: Mutex::new(props.create()),
             mailbox: Mutex::new(VecDeque::new()),
+            stash: Mutex::new(VecDeque::new()),
             system_mailbox: Mutex::new(VecDeque::new()),
             props: props,
             system: system,
@@ -372,12 +703,24 @@ impl InnerActorCell {
             busy: Mutex::new(()),
             father: father.clone(),
             children: Mutex::new(Vec::new()),
+            anonymous_children: AtomicUsize::new(0),
             monitoring: Mutex::new(Vec::new()),
+            watchers: Mutex::new(Vec::new()),
+            uid: uid,
             actor_state: Arc::new(RwLock::new(ActorState::Unstarted)),
+            restarts: AtomicUsize::new(0),
+            pending: Mutex::new(None),
+            suspended: AtomicBool::new(false),
+            deferred: AtomicUsize::new(0),
             _monitored: Mutex::new(vec![father.clone()]),
         }
     }
 
+    fn actor<'a>(&'a self) -> MutexGuard<'a, ActorInstance> {
+        // The lock is poisoned when the actor panicked, it is still given to `pre_restart`.
+        self.actor.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
     fn receive_envelope(&self, envelope: Envelope) {
         self.mailbox.lock().unwrap().push_back(envelope);
     }
@@ -386,6 +729,7 @@ impl InnerActorCell {
         self.receive_envelope(Envelope {
             message: message,
             sender: sender,
+            enqueued: Instant::now(),
         });
     }
 
@@ -393,6 +737,18 @@ impl InnerActorCell {
         self.system_mailbox.lock().unwrap().push_back(system_message);
     }
 
+    /// Puts the stashed messages back at the front of the mailbox and returns how many there
+    /// were.
+    fn unstash_all(&self) -> usize {
+        let mut stash = self.stash.lock().unwrap();
+        let mut mailbox = self.mailbox.lock().unwrap();
+        let unstashed = stash.len();
+        while let Some(envelope) = stash.pop_back() {
+            mailbox.push_front(envelope);
+        }
+        unstashed
+    }
+
     fn handle_envelope(&self, context: ActorCell) {
         // Now we do not want users to be able to touch current_sender while the actor is busy.
         let _lock = self.busy.lock();
@@ -411,11 +767,33 @@ impl InnerActorCell {
                 SystemMessage::Failure(actor) => {
                     actor.receive_system_message(SystemMessage::Restart)
                 }
+                SystemMessage::Watch(watcher) => {
+                    let mut watchers = self.watchers.lock().unwrap();
+                    if !watchers.contains(&watcher) {
+                        watchers.push(watcher);
+                    }
+                }
+                SystemMessage::Unwatch(watcher) => {
+                    self.watchers.lock().unwrap().retain(|w| *w != watcher);
+                }
+                SystemMessage::Resume => {
+                    // The future may already be completed if it was woken several times.
+                    let pending = self.pending.lock().unwrap().take();
+                    if let Some(future) = pending {
+                        self.poll_pending(future, &context);
+                    }
+                }
             }
             failsafe.cancel();
             return;
         }
 
+        if self.suspended.load(Ordering::SeqCst) {
+            self.deferred.fetch_add(1, Ordering::SeqCst);
+            failsafe.cancel();
+            return;
+        }
+
         if *self.actor_state.read().unwrap() == ActorState::Running {
             let envelope = match self.mailbox.lock().unwrap().pop_front() {
                 Some(envelope) => envelope,
@@ -428,21 +806,44 @@ impl InnerActorCell {
                 let mut current_sender = self.current_sender.lock().unwrap();
                 *current_sender = Some(envelope.sender.clone());
             };
+            let started = Instant::now();
+            let _handling = self.system.watchdog().begin(&self.system,
+                                                         self.path.clone(),
+                                                         inner_message_type_name(&envelope.message));
             {
-                let actor = self.actor.read().unwrap();
+                let mut actor = self.actor();
                 match envelope.message {
                     InnerMessage::Message(message) => {
-                        actor.receive(message, context);
+                        // The replies received by a message adapter are converted here, so that
+                        // the adapter runs as part of the handling of the message.
+                        let message = match message.downcast::<AdaptedReply>() {
+                            Ok(reply) => reply.adapt(),
+                            Err(message) => message,
+                        };
+                        if actor.stashes(&*message) {
+                            self.stash.lock().unwrap().push_back(Envelope {
+                                message: InnerMessage::Message(message),
+                                sender: envelope.sender,
+                                enqueued: envelope.enqueued,
+                            });
+                        } else if let Some(future) = actor.receive_async(message, context.clone()) {
+                            self.poll_pending(future, &context);
+                        }
                     },
                     InnerMessage::Control(message) => {
                         match message {
                             ControlMessage::PoisonPill => context.kill_me(),
-                            ControlMessage::Terminated(_) => actor.receive_termination(context),
+                            ControlMessage::Terminated(actor_ref) => {
+                                actor.receive_termination(actor_ref, context)
+                            }
                             ControlMessage::KillMe(actor_ref) => self.kill(actor_ref, context),
                         }
                     }
                 }
             }
+            self.system.metrics().record_message(&self.path,
+                                                 started.duration_since(envelope.enqueued),
+                                                 started.elapsed());
         } else {
             self.system.enqueue_actor(context.actor_ref());
         }
@@ -466,16 +867,70 @@ impl InnerActorCell {
     }
 
     fn start(&self, context: ActorCell) {
-        self.actor.write().unwrap().pre_start(context);
+        self.actor().pre_start(context);
         *self.actor_state.write().unwrap() = ActorState::Running;
     }
 
     fn restart(&self, context: ActorCell) {
-        let mut actor = self.actor.write().unwrap();
+        let mut actor = self.actor();
         actor.pre_restart(context.clone());
         *actor = self.props.create();
-        actor.post_restart(context);
+        // The new instance starts from a clean state, the stashed messages are thus given back.
+        for _ in 0..self.unstash_all() {
+            self.system.enqueue_actor(context.actor_ref());
+        }
+        actor.post_restart(context.clone());
         *self.actor_state.write().unwrap() = ActorState::Running;
+        self.restarts.fetch_add(1, Ordering::SeqCst);
+        // The new actor does not continue the message the failed one was handling.
+        self.pending.lock().unwrap().take();
+        if self.suspended.load(Ordering::SeqCst) {
+            self.resume_mailbox(&context);
+        }
+        self.system.metrics().record_restart(&self.path);
+    }
+
+    /// Polls the future of the message being handled, the actor stays suspended until it is
+    /// completed.
+    fn poll_pending(&self, mut future: BoxFuture, context: &ActorCell) {
+        let waker = ResumeWaker::waker(context.actor_ref());
+        let mut cx = Context::from_waker(&waker);
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(()) => self.resume_mailbox(context),
+            Poll::Pending => {
+                // If the future is woken right away the Resume is handled once we are done here,
+                // as we hold the busy lock.
+                *self.pending.lock().unwrap() = Some(future);
+                self.suspended.store(true, Ordering::SeqCst);
+            }
+        }
+    }
+
+    /// Handles the messages again, the actor is scheduled for the ones deferred while suspended.
+    fn resume_mailbox(&self, context: &ActorCell) {
+        self.suspended.store(false, Ordering::SeqCst);
+        for _ in 0..self.deferred.swap(0, Ordering::SeqCst) {
+            self.system.enqueue_actor(context.actor_ref());
+        }
+    }
+
+    fn snapshot(&self) -> ActorSnapshot {
+        // The children are snapshotted without holding our lock, they may need it to terminate.
+        let children: Vec<ActorRef> = self.children
+                                           .lock()
+                                           .unwrap()
+                                           .iter()
+                                           .map(|&(_, ref child)| child.clone())
+                                           .collect();
+        ActorSnapshot {
+            path: self.path.clone(),
+            uid: self.uid,
+            state: *self.actor_state.read().unwrap(),
+            mailbox_len: self.mailbox.lock().unwrap().len(),
+            system_mailbox_len: self.system_mailbox.lock().unwrap().len(),
+            restart_count: self.restarts.load(Ordering::SeqCst),
+            children: children.iter().filter_map(|child| child.snapshot()).collect(),
+        }
     }
 }
 
@@ -483,8 +938,20 @@ impl Drop for InnerActorCell {
     fn drop(&mut self) {
         // FIXME(gamazeps) Looking at the logs it seems as though fathers are killed before their
         // children, that is not the intended behaviour.
-        let actor = self.actor.write().unwrap();
+        let actor = self.actor.get_mut().unwrap_or_else(|poisoned| poisoned.into_inner());
         // println!("Actor {} is dropped", *self._name);
         actor.post_stop();
+        // The cell is being dropped so we cannot give a working ref to the actor, but this one
+        // has the right identity.
+        let actor_cell = ActorCell {
+            inner_cell: Ref::WeakRef(Weak::new()),
+            system: self.system.clone(),
+            uid: self.uid,
+        };
+        let actor_ref = ActorRef::with_cell(actor_cell, self.path.clone());
+        for watcher in self.watchers.lock().unwrap().drain(..) {
+            watcher.receive(InnerMessage::Control(ControlMessage::Terminated(actor_ref.clone())),
+                            actor_ref.clone());
+        }
     }
 }
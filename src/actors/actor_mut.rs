@@ -0,0 +1,70 @@
+use std::any::Any;
+use std::sync::Arc;
+
+use actors::{ActorCell, ActorRef, Arguments};
+use actors::event_stream::publish_unhandled;
+use actors::props::{ActorFactory, ActorInstance};
+
+/// Trait to implement for actors mutating their state, it is the equivalent of `Actor` with
+/// methods taking `&mut self`.
+///
+/// The actor is owned by its cell, which never handles two of its messages at the same time, so
+/// the state can be kept in plain fields instead of `Mutex`es. As the actor is never shared it only
+/// needs to be `Send`.
+///
+/// Such actors are created with `MutProps`.
+pub trait ActorMut: Send + 'static {
+    /// Handles a message, the ones it does not handle should be given to `unhandled`.
+    fn receive(&mut self, message: Box<Any>, context: ActorCell);
+
+    /// Method called with the messages `receive` does not handle.
+    ///
+    /// By default it publishes an `UnhandledMessage` event, as `Actor::unhandled`.
+    fn unhandled(&mut self, message: Box<Any>, context: ActorCell) {
+        publish_unhandled(&*message, &context);
+    }
+
+    /// Method called when a monitored actor is terminated, as `Actor::receive_termination`.
+    fn receive_termination(&mut self, _terminated: ActorRef, _context: ActorCell) {
+        panic!("Not implemented");
+    }
+
+    /// Method called before the Actor is started.
+    fn pre_start(&mut self, _context: ActorCell) {}
+
+    /// Method called after the Actor is stopped.
+    fn post_stop(&mut self) {}
+
+    /// Method called before the Actor is restarted.
+    fn pre_restart(&mut self, _context: ActorCell) {
+        self.post_stop();
+    }
+
+    /// Method called after the Actor is restarted.
+    fn post_restart(&mut self, context: ActorCell) {
+        self.pre_start(context);
+    }
+}
+
+/// Factory for ActorMuts, it is the equivalent of `Props`.
+pub struct MutProps<Args: Arguments, A: ActorMut> {
+    creator: Arc<Fn(Args) -> A + Sync + Send>,
+    args: Args,
+}
+
+impl<Args: Arguments, A: ActorMut> MutProps<Args, A> {
+    /// Creates a `MutProps` which is a factory for `A` with the `creator` function and `args`
+    /// args.
+    pub fn new(creator: Arc<Fn(Args) -> A + Sync + Send>, args: Args) -> Arc<ActorFactory> {
+        Arc::new(MutProps::<Args, A> {
+            creator: creator,
+            args: args,
+        })
+    }
+}
+
+impl<Args: Arguments, A: ActorMut> ActorFactory for MutProps<Args, A> {
+    fn create(&self) -> ActorInstance {
+        ActorInstance::Owned(Box::new((self.creator)(self.args.clone())))
+    }
+}
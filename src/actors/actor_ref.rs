@@ -1,15 +1,21 @@
 extern crate eventual;
 
-use self::eventual::{Complete, Future};
+use self::eventual::{Async, Complete, Future};
 
 use std::any::Any;
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::sync::{Arc, Mutex};
 
-use actors::{ActorContext, InnerMessage, Message, SystemMessage};
-use actors::actor_cell::ActorCell;
+use actors::{ActorSnapshot, ControlMessage, EventualFuture, InnerMessage, Message, ReplyTo,
+             SystemMessage};
+use actors::actor_cell::{new_uid, ActorCell};
+use actors::blocking::AskFuture;
 use actors::cthulhu::Cthulhu;
+use actors::watchdog::register_message_type;
 
-#[derive(Debug, Eq, Hash, PartialEq)]
+#[derive(Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 /// Path to an actor.
 ///
 /// This enum contains the information for actors whether they are local or distant.
@@ -59,7 +65,16 @@ impl ActorPath {
     }
 }
 
-#[derive(Debug, Eq, Hash, PartialEq)]
+impl fmt::Display for ActorPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ActorPath::Local(ref s) => write!(f, "{}", s),
+            ActorPath::Distant(ref c) => write!(f, "{}{}", c.addr_port, c.distant_logical_path),
+        }
+    }
+}
+
+#[derive(Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 /// This gives connection informations on how to get to the distant actors.
 ///
 /// *  The distant_logical_path is something like "/user/distant/actor".
@@ -83,11 +98,24 @@ impl ConnectionInfo {
     }
 }
 
+/// Something that is not an actor but can be given an ActorRef to receive messages, such as a
+/// test probe.
+///
+/// Messages are given to it directly by the thread sending them, it is never scheduled.
+pub trait MessageReceiver: Send + Sync {
+    /// Receives a message sent to `receiver`, the ActorRef of the MessageReceiver.
+    ///
+    /// The ref is given here rather than stored in the MessageReceiver, as it holds the
+    /// MessageReceiver itself.
+    fn receive(&self, message: InnerMessage, sender: ActorRef, receiver: &ActorRef);
+}
+
 #[derive(Clone)]
 enum InnerActor {
     Cthulhu(Cthulhu),
     Actor(ActorCell),
     Complete(CompleteRef),
+    Receiver(Arc<MessageReceiver>),
 }
 
 #[derive(Clone)]
@@ -125,9 +153,13 @@ impl CompleteRef {
 ///
 /// It gives the Actor API, it can receive messages, be told to send messages, be asked something
 /// and give its ActorPath.
+///
+/// Two ActorRefs are equal if they have the same path and point to the same incarnation of the
+/// actor (see `uid`).
 pub struct ActorRef {
     inner_actor: Option<InnerActor>,
     path: Arc<ActorPath>,
+    uid: u64,
 }
 
 impl ActorRef {
@@ -136,6 +168,7 @@ impl ActorRef {
         ActorRef {
             inner_actor: None,
             path: path,
+            uid: 0,
         }
     }
 
@@ -145,12 +178,14 @@ impl ActorRef {
         ActorRef {
             inner_actor: Some(InnerActor::Cthulhu(cthulhu)),
             path: path,
+            uid: 0,
         }
     }
 
     /// Creates a new ActorRef for a local Actor, with the given ActorCell.
     pub fn with_cell(cell: ActorCell, path: Arc<ActorPath>) -> ActorRef {
         ActorRef {
+            uid: cell.uid(),
             inner_actor: Some(InnerActor::Actor(cell)),
             path: path,
         }
@@ -163,6 +198,26 @@ impl ActorRef {
             inner_actor: Some(InnerActor::Complete(CompleteRef::new(complete))),
             // FIXME(gamazeps) future registration is not working here, this is not cool.
             path: ActorPath::new_local("local_future".to_owned()),
+            // All futures share their path, so the uid is what tells them apart.
+            uid: new_uid(),
+        }
+    }
+
+    /// Creates a new ActorRef to a local MessageReceiver, with the given ActorPath.
+    pub fn with_receiver(receiver: Arc<MessageReceiver>, path: Arc<ActorPath>) -> ActorRef {
+        ActorRef {
+            inner_actor: Some(InnerActor::Receiver(receiver)),
+            path: path,
+            uid: new_uid(),
+        }
+    }
+
+    /// Takes a snapshot of the actor and of its descendants, None if this is not a ref to a living
+    /// local actor.
+    pub fn snapshot(&self) -> Option<ActorSnapshot> {
+        match self.inner_actor {
+            Some(InnerActor::Actor(ref cell)) if cell.uid() == self.uid => cell.snapshot(),
+            _ => None,
         }
     }
 
@@ -172,7 +227,21 @@ impl ActorRef {
         let inner = self.inner_actor.as_ref().expect("Tried to put a system message in the mailbox of a distant actor.");
         match *inner {
             InnerActor::Complete(_) => panic!("Futures should not receive system messages."),
-            InnerActor::Actor(ref actor) => actor.receive_system_message(system_message),
+            // Message receivers have no lifecycle, there is nothing to do.
+            InnerActor::Receiver(_) => {}
+            InnerActor::Actor(ref actor) => {
+                match actor.receive_system_message(system_message) {
+                    Ok(()) => {}
+                    // Watching a stopped actor gives the termination notice right away.
+                    Err(SystemMessage::Watch(watcher)) => {
+                        watcher.receive(InnerMessage::Control(ControlMessage::Terminated(self.clone())),
+                                        self.clone())
+                    }
+                    // A future of a stopped actor was woken, there is nothing to resume.
+                    Err(SystemMessage::Resume) => {}
+                    Err(_) => println!("A message was send to a ref to a stopped actor"),
+                }
+            }
             InnerActor::Cthulhu(ref cthulhu) => cthulhu.receive_system_message(),
         };
     }
@@ -182,7 +251,14 @@ impl ActorRef {
         let inner = self.inner_actor.as_ref().expect("Tried to put a message in the mailbox of a distant actor.");
         match *inner {
             InnerActor::Complete(ref complete) => complete.complete(message),
-            InnerActor::Actor(ref actor) => actor.receive_message(message, sender),
+            InnerActor::Receiver(ref receiver) => receiver.receive(message, sender, self),
+            InnerActor::Actor(ref actor) => {
+                // The incarnation this ref points to is stopped, even if another actor now lives
+                // at the same path the message must not be given to it.
+                if let Err(message) = actor.receive_message(message, sender.clone()) {
+                    actor.system().dead_letter(message, sender, self.clone());
+                }
+            }
             InnerActor::Cthulhu(ref cthulhu) => cthulhu.receive(),
         };
     }
@@ -192,6 +268,7 @@ impl ActorRef {
         let inner = self.inner_actor.as_ref().expect("");
         match *inner {
             InnerActor::Complete(_) => panic!("In the current model futures should not handle messages."),
+            InnerActor::Receiver(_) => panic!("Message receivers are never scheduled."),
             InnerActor::Actor(ref actor) => actor.handle_envelope(),
             InnerActor::Cthulhu(ref cthulhu) => cthulhu.handle(),
         };
@@ -202,12 +279,21 @@ impl ActorRef {
         self.path.clone()
     }
 
+    /// Unique identifier of the incarnation of the actor this ref points to.
+    ///
+    /// Refs to futures and message receivers get a uid of their own, refs to distant actors and to
+    /// Cthulhu have a uid of 0 and are only identified by their path.
+    pub fn uid(&self) -> u64 {
+        self.uid
+    }
+
     /// Makes this ActorRef send a message to anther ActorRef.
     pub fn tell_to<MessageTo: Message>(&self, to: ActorRef, message: MessageTo) {
         let inner = self.inner_actor.as_ref().expect("");
         match *inner {
             InnerActor::Complete(_) => panic!("A future should not be sending a message to an actor this way."),
-            InnerActor::Actor(_) => {
+            InnerActor::Actor(_) | InnerActor::Receiver(_) => {
+                register_message_type::<MessageTo>();
                 // This is done in order to avoid a trivial cast warning.
                 let message: Box<Any + Send> = Box::new(message);
                 to.receive(InnerMessage::Message(message), self.clone())
@@ -216,15 +302,51 @@ impl ActorRef {
         };
     }
 
-    /// Sends a message to an ActorRef, the answer to this message will be put in the `Future` given
-    /// as return value.
-    pub fn ask<MessageTo: Message>(&self, message: MessageTo)
-        -> Future<Box<Any + Send>, &'static str> {
+    /// Same as `ask`, with a `std::future::Future` which can be awaited in async Rust.
+    ///
+    /// As awaiting does not block a thread, the `BlockingAskPolicy` is not applied.
+    pub fn ask_async<MessageTo: Message>(&self, message: MessageTo)
+        -> EventualFuture<Box<Any + Send>, &'static str> {
+        EventualFuture::new(self.ask(message))
+    }
+
+    /// Sends a message to an ActorRef, the answer to this message will be put in the `AskFuture`
+    /// given as return value.
+    ///
+    /// Waiting for the answer with `await` while handling a message blocks a consumer thread and
+    /// may deadlock the actor system, the `BlockingAskPolicy` of the actor system is then applied.
+    pub fn ask<MessageTo: Message>(&self, message: MessageTo) -> AskFuture<Box<Any + Send>> {
         let (complete, future) = Future::<Box<Any + Send>, &'static str>::pair();
+        register_message_type::<MessageTo>();
         // This is done in order to avoid a trivial cast warning.
         let message: Box<Any + Send> = Box::new(message);
         self.receive(InnerMessage::Message(message), ActorRef::with_complete(complete));
-        future
+        AskFuture::new(future, self.path.clone())
+    }
+
+    /// Sends the request built by `request` with the `ReplyTo` of a future, the typed reply to
+    /// this request is put in the `AskFuture` given as return value.
+    ///
+    /// As with `ask`, the `BlockingAskPolicy` is applied when its answer is awaited while handling
+    /// a message.
+    pub fn ask_typed<MessageTo, Reply, F>(&self, request: F) -> AskFuture<Reply>
+        where MessageTo: Message,
+              Reply: Message,
+              F: FnOnce(ReplyTo<Reply>) -> MessageTo
+    {
+        let (complete, future) = Future::<Box<Any + Send>, &'static str>::pair();
+        let future_ref = ActorRef::with_complete(complete);
+        register_message_type::<MessageTo>();
+        // This is done in order to avoid a trivial cast warning.
+        let message: Box<Any + Send> = Box::new(request(ReplyTo::new(future_ref.clone())));
+        self.receive(InnerMessage::Message(message), future_ref);
+        let future = future.and_then(|reply| {
+            match reply.downcast::<Reply>() {
+                Ok(reply) => Ok(*reply),
+                Err(_) => Err("The reply is not of the type given by the ReplyTo"),
+            }
+        });
+        AskFuture::new(future, self.path.clone())
     }
 }
 
@@ -233,6 +355,46 @@ impl Clone for ActorRef {
         ActorRef {
             inner_actor: self.inner_actor.clone(),
             path: self.path.clone(),
+            uid: self.uid,
         }
     }
 }
+
+impl PartialEq for ActorRef {
+    fn eq(&self, other: &ActorRef) -> bool {
+        self.uid == other.uid && self.path == other.path
+    }
+}
+
+impl Eq for ActorRef {}
+
+impl Hash for ActorRef {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.path.hash(state);
+        self.uid.hash(state);
+    }
+}
+
+impl PartialOrd for ActorRef {
+    fn partial_cmp(&self, other: &ActorRef) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ActorRef {
+    fn cmp(&self, other: &ActorRef) -> Ordering {
+        self.path.cmp(&other.path).then(self.uid.cmp(&other.uid))
+    }
+}
+
+impl fmt::Display for ActorRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Actor[{}#{}]", self.path, self.uid)
+    }
+}
+
+impl fmt::Debug for ActorRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
@@ -1,15 +1,32 @@
-use std::any::Any;
+extern crate rand;
+#[cfg(feature = "tokio")]
+extern crate tokio;
+
+use self::rand::{Rng, SeedableRng, StdRng};
+#[cfg(feature = "tokio")]
+use self::tokio::runtime::Handle;
+
+use std::panic::{self, AssertUnwindSafe};
 use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
 use std::thread;
+use std::time::{Duration, Instant};
 
-use actors::{ActorPath, ActorRef, Props};
-use actors::actor_cell::{ActorCell, SystemMessage};
-use actors::actor_ref::eventual::Async;
+use actors::{ActorContext, ActorCreationError, ActorPath, ActorRef, ControlMessage, EventStream,
+             Extension, Extensions, Inbox, Message, MessageReceiver, Props, SystemSnapshot};
+use actors::actor_cell::{send_message, ActorCell, InnerMessage, SystemMessage};
+use actors::blocking::{BlockingAskPolicy, HandlingMarker};
 use actors::cthulhu::Cthulhu;
+use actors::dead_letters::{DeadLetter, DeadLetters};
+use actors::metrics::Metrics;
 use actors::name_resolver::NameResolver;
 use actors::props::ActorFactory;
 use actors::root_actor::RootActor;
+use actors::scheduler::Scheduler;
+use actors::watchdog::{register_message_type, Watchdog};
+#[cfg(feature = "tokio")]
+use actors::tokio_bridge::TokioDispatcher;
 
 /// This is failsafe used to relaunch consumer threads if they panic!.
 struct Relauncher {
@@ -49,6 +66,13 @@ impl Drop for Relauncher {
 ///
 /// Calling `shutdown`, will drop all the actors and terminate the consumer threads.
 /// Note that it will shut down the system even if some actors have still messages to handle.
+///
+/// For tests, an actor system can be created with `new_deterministic`: it then has no consumer
+/// thread, the actors handle their messages when the test calls `step`, `run_until_idle` or
+/// `advance_time`, and the scheduler follows a virtual clock.
+///
+/// With the `tokio` feature, an actor system created with `with_tokio` runs the actors on a tokio
+/// runtime instead of consumer threads.
 pub struct ActorSystem {
     inner: Arc<InnerActorSystem>,
 }
@@ -58,42 +82,131 @@ impl ActorSystem {
     ///
     /// Note that one thread is started.
     pub fn new(name: String) -> ActorSystem {
-        let actor_system = ActorSystem { inner: Arc::new(InnerActorSystem::new(name)) };
+        ActorSystem::with_inner(InnerActorSystem::new(name, None))
+    }
+
+    /// Creates a new deterministic ActorSystem, meant for tests.
+    ///
+    /// No consumer thread is started (and `spawn_threads` does nothing), the actors are run by the
+    /// thread calling `step`, `run_until_idle` or `advance_time`. When several actors have messages
+    /// to handle, the one to run is chosen randomly from `seed`, so that running a test again with
+    /// the same seed gives the same interleaving.
+    ///
+    /// The scheduler follows a virtual clock which only moves with `advance_time`.
+    ///
+    /// Note that blocking on a future from inside an actor (such as with `identify_actor`) is not
+    /// supported in this mode, as no other actor can run meanwhile.
+    pub fn new_deterministic(name: String, seed: u64) -> ActorSystem {
+        ActorSystem::with_inner(InnerActorSystem::new(name, Some(DeterministicDispatcher::new(seed))))
+    }
+
+    /// Creates a new ActorSystem running the actors on the tokio runtime of `handle`.
+    ///
+    /// No consumer thread is started (and `spawn_threads` does nothing), each time an actor has a
+    /// message to handle the turn is run on the runtime. The runtime is not shut down with the
+    /// actor system.
+    #[cfg(feature = "tokio")]
+    pub fn with_tokio(name: String, handle: Handle) -> ActorSystem {
+        let mut inner = InnerActorSystem::new(name, None);
+        inner.tokio = Some(TokioDispatcher::new(handle));
+        ActorSystem::with_inner(inner)
+    }
+
+    fn with_inner(inner: InnerActorSystem) -> ActorSystem {
+        let actor_system = ActorSystem { inner: Arc::new(inner) };
         let cthulhu = Cthulhu::new(actor_system.clone());
         let cthulhu = ActorRef::with_cthulhu(cthulhu);
         *actor_system.inner.cthulhu.write().unwrap() = Some(cthulhu.clone());
-        let user_actor_path = ActorPath::new_local("/user".to_owned());
-        let user_actor_cell = ActorCell::new(Props::new(Arc::new(RootActor::new), ()),
-                                                actor_system.clone(),
-                                                cthulhu.clone(),
-                                                user_actor_path.clone());
-        let user_actor = ActorRef::with_cell(user_actor_cell, user_actor_path);
-        user_actor.receive_system_message(SystemMessage::Start);
+        let (user_actor_cell, user_actor) = actor_system.root_actor("/user", cthulhu.clone());
+        *actor_system.inner.user_actor_cell.write().unwrap() = Some(user_actor_cell);
         *actor_system.inner.user_actor.write().unwrap() = Some(user_actor);
-        let system_actor_path = ActorPath::new_local("/system".to_owned());
-        let system_actor_cell = ActorCell::new(Props::new(Arc::new(RootActor::new), ()),
-                                                actor_system.clone(),
-                                                cthulhu.clone(),
-                                                system_actor_path.clone());
-        let system_actor = ActorRef::with_cell(system_actor_cell, system_actor_path);
-        system_actor.receive_system_message(SystemMessage::Start);
+        let (system_actor_cell, system_actor) = actor_system.root_actor("/system", cthulhu.clone());
+        *actor_system.inner.system_actor_cell.write().unwrap() = Some(system_actor_cell);
         *actor_system.inner.system_actor.write().unwrap() = Some(system_actor);
+        let (temp_actor_cell, temp_actor) = actor_system.root_actor("/temp", cthulhu.clone());
+        *actor_system.inner.temp_actor_cell.write().unwrap() = Some(temp_actor_cell);
+        *actor_system.inner.temp_actor.write().unwrap() = Some(temp_actor);
         actor_system.spawn_threads(1);
-        let name_resolver = actor_system.system_actor_of(Props::new(Arc::new(NameResolver::new), ()), "name_resolver".to_owned());
+        let name_resolver = actor_system.system_actor_of(Props::new(Arc::new(NameResolver::new), ()), "name_resolver".to_owned())
+                                        .unwrap();
         *actor_system.inner.name_resolver.write().unwrap() = Some(name_resolver);
+        let dead_letters = actor_system.system_actor_of(Props::new(Arc::new(DeadLetters::new), ()), "dead_letters".to_owned())
+                                       .unwrap();
+        *actor_system.inner.dead_letters.write().unwrap() = Some(dead_letters);
         actor_system
     }
 
+    /// Creates and starts a root actor at `path`, returns its cell (only keeping a weak reference)
+    /// and the ref owning it.
+    fn root_actor(&self, path: &str, cthulhu: ActorRef) -> (ActorCell, ActorRef) {
+        let path = ActorPath::new_local(path.to_owned());
+        let cell = ActorCell::new(Props::new(Arc::new(RootActor::new), ()),
+                                  self.clone(),
+                                  cthulhu,
+                                  path.clone());
+        let weak_cell = cell.clone();
+        let actor_ref = ActorRef::with_cell(cell, path);
+        actor_ref.receive_system_message(SystemMessage::Start);
+        (weak_cell, actor_ref)
+    }
+
     /// Spawns an Actor created using the Props given for the user.
-    pub fn actor_of(&self, props: Arc<ActorFactory>, name: String) -> ActorRef {
+    ///
+    /// This fails if the name is invalid or already taken by another top level user actor.
+    ///
+    /// The actor is created without waiting for the user actor, so this can be called from any
+    /// thread, including from inside an actor. It starts once a consumer thread handles its
+    /// `Start` system message, the messages sent to it meanwhile wait in its mailbox.
+    pub fn actor_of(&self, props: Arc<ActorFactory>, name: String) -> Result<ActorRef, ActorCreationError> {
         self.inner.actor_of(props, name)
     }
 
+    /// Spawns an Actor created using the Props given for the user, with a generated unique name
+    /// such as `$a` or `$b`.
+    pub fn actor_of_anonymous(&self, props: Arc<ActorFactory>) -> ActorRef {
+        self.inner.actor_of_anonymous(props)
+    }
+
     /// Spawns an Actor created using the Props given for the system.
-    pub fn system_actor_of(&self, props: Arc<ActorFactory>, name: String) -> ActorRef {
+    ///
+    /// This fails if the name is invalid or already taken by another top level system actor.
+    pub fn system_actor_of(&self, props: Arc<ActorFactory>, name: String) -> Result<ActorRef, ActorCreationError> {
         self.inner.system_actor_of(props, name)
     }
 
+    /// Registers `receiver` under `/system` with a generated name, such as the receivers of the
+    /// `Inbox`es, so that its path can be resolved.
+    ///
+    /// It stays registered until it is stopped with `stop_system_receiver`.
+    pub fn system_receiver_of_anonymous(&self, receiver: Arc<MessageReceiver>) -> ActorRef {
+        match self.inner.system_actor_cell.read().unwrap().clone() {
+            Some(system_actor) => system_actor.receiver_of_anonymous(receiver),
+            None => panic!("The system actor is not initialised"),
+        }
+    }
+
+    /// Stops a receiver registered with `system_receiver_of_anonymous`.
+    ///
+    /// This does nothing once the actor system is shut down, the receivers are then already gone.
+    pub fn stop_system_receiver(&self, receiver: &ActorRef) {
+        if let Some(system_actor) = self.inner.system_actor.read().unwrap().clone() {
+            system_actor.receive(InnerMessage::Control(ControlMessage::KillMe(receiver.clone())),
+                                 receiver.clone());
+        }
+    }
+
+    /// Spawns a temporary actor under `/temp` with a generated name, such as the actors used by
+    /// `ActorContext::ask_with`.
+    ///
+    /// Temporary actors have a resolvable path, they are expected to stop themselves once their
+    /// work is done.
+    pub fn temp_actor_of(&self, props: Arc<ActorFactory>) -> ActorRef {
+        match self.inner.temp_actor_cell.read().unwrap().clone() {
+            Some(temp_actor) => temp_actor.actor_of_anonymous(props),
+            None => panic!("The temp actor is not initialised"),
+        }
+    }
+
     /// Shuts the actor system down.
     ///
     /// It will terminate all the actors (whether they still have messages to handle or not) and
@@ -104,6 +217,13 @@ impl ActorSystem {
 
     /// Enqueues the given ActorRef in the queue of ActorRef with message to handle.
     pub fn enqueue_actor(&self, actor_ref: ActorRef) {
+        #[cfg(feature = "tokio")]
+        {
+            if let Some(ref dispatcher) = self.inner.tokio {
+                dispatcher.dispatch(self.clone(), actor_ref);
+                return;
+            }
+        }
         self.inner.enqueue_actor(actor_ref);
     }
 
@@ -111,17 +231,23 @@ impl ActorSystem {
     ///
     /// This thread can be terminated by calling `terminate_thread`.
     pub fn spawn_thread(&self) {
+        if self.is_deterministic() || self.is_on_tokio() {
+            return;
+        }
         let actors_queue = self.inner.actors_queue_receiver.clone();
         let rx = self.inner.consumer_threads_receiver.clone();
         let actor_system = self.clone();
+        let metrics = self.metrics();
         let _ = thread::spawn(move || {
             // This is a failsafe used to relaunch a consumer thread if it panic!
             let relauncher = Relauncher::new(actor_system.clone());
+            let thread_id = metrics.register_thread();
             loop {
                 // We check if we received a termination request.
                 match rx.lock().unwrap().try_recv() {
                     Ok(_) | Err(TryRecvError::Disconnected) => {
                         relauncher.cancel();
+                        metrics.record_thread_stopped(thread_id);
                         break;
                     }
                     Err(TryRecvError::Empty) => {}
@@ -134,7 +260,12 @@ impl ActorSystem {
                 };
 
                 match actor_ref {
-                    Ok(actor_ref) => actor_ref.handle(),
+                    Ok(actor_ref) => {
+                        let started = Instant::now();
+                        let _marker = HandlingMarker::enter(actor_system.clone(), actor_ref.path());
+                        actor_ref.handle();
+                        metrics.record_thread_busy(thread_id, started.elapsed());
+                    }
                     Err(TryRecvError::Empty) => continue,
                     Err(TryRecvError::Disconnected) => {
                         relauncher.cancel();
@@ -171,6 +302,250 @@ impl ActorSystem {
             Some(resolver) => resolver.clone(),
         }
     }
+
+    /// Whether the actor system was created with `new_deterministic`.
+    pub fn is_deterministic(&self) -> bool {
+        self.inner.deterministic.is_some()
+    }
+
+    /// Whether the actor system was created with `with_tokio`.
+    #[cfg(feature = "tokio")]
+    pub fn is_on_tokio(&self) -> bool {
+        self.inner.tokio.is_some()
+    }
+
+    /// Whether the actor system was created with `with_tokio`, which needs the `tokio` feature.
+    #[cfg(not(feature = "tokio"))]
+    pub fn is_on_tokio(&self) -> bool {
+        false
+    }
+
+    /// Handle of the tokio runtime the actors are run on.
+    #[cfg(feature = "tokio")]
+    pub fn tokio_handle(&self) -> Option<Handle> {
+        self.inner.tokio.as_ref().map(|dispatcher| dispatcher.handle().clone())
+    }
+
+    /// Seed of a deterministic actor system.
+    pub fn seed(&self) -> Option<u64> {
+        self.inner.deterministic.as_ref().map(|dispatcher| dispatcher.seed)
+    }
+
+    /// Number of actors waiting to handle a message in a deterministic actor system, an actor is
+    /// counted once per message.
+    pub fn pending(&self) -> usize {
+        self.deterministic().run_queue.lock().unwrap().len()
+    }
+
+    /// Has one actor handle one message in a deterministic actor system.
+    ///
+    /// Returns the path of the actor, or None if no actor had a message to handle. A panic of the
+    /// actor is caught, the actor is then restarted as usual.
+    pub fn step(&self) -> Option<Arc<ActorPath>> {
+        let actor_ref = {
+            let dispatcher = self.deterministic();
+            let mut run_queue = dispatcher.run_queue.lock().unwrap();
+            if run_queue.is_empty() {
+                return None;
+            }
+            let i = dispatcher.rng.lock().unwrap().gen_range(0, run_queue.len());
+            run_queue.remove(i)
+        };
+        {
+            let _marker = HandlingMarker::enter(self.clone(), actor_ref.path());
+            let _ = panic::catch_unwind(AssertUnwindSafe(|| actor_ref.handle()));
+        }
+        Some(actor_ref.path())
+    }
+
+    /// Runs `step` until no actor has messages to handle, returns the number of steps.
+    pub fn run_until_idle(&self) -> usize {
+        let mut steps = 0;
+        while self.step().is_some() {
+            steps += 1;
+        }
+        steps
+    }
+
+    /// Moves the virtual clock of a deterministic actor system forward by `duration`.
+    ///
+    /// The scheduled tasks (messages sent with `schedule_once`, state timeouts, ...) that are due
+    /// are run in order, and the actors handle all their messages after each of them.
+    pub fn advance_time(&self, duration: Duration) {
+        self.deterministic();
+        let scheduler = self.scheduler();
+        let target = scheduler.now() + duration;
+        self.run_until_idle();
+        loop {
+            let now = scheduler.now();
+            match scheduler.next_deadline() {
+                Some(deadline) if deadline <= target => {
+                    scheduler.advance_time(if deadline > now {
+                        deadline - now
+                    } else {
+                        Duration::from_millis(0)
+                    })
+                }
+                _ => break,
+            }
+            self.run_until_idle();
+        }
+        let now = scheduler.now();
+        scheduler.advance_time(target - now);
+        self.run_until_idle();
+    }
+
+    fn deterministic(&self) -> &DeterministicDispatcher {
+        match self.inner.deterministic {
+            Some(ref dispatcher) => dispatcher,
+            None => panic!("This can only be done with a deterministic actor system."),
+        }
+    }
+
+    /// Sets what to do when an actor blocks on the answer of an ask while handling a message.
+    pub fn set_blocking_ask_policy(&self, policy: BlockingAskPolicy) {
+        *self.inner.blocking_ask_policy.write().unwrap() = policy;
+    }
+
+    /// What is done when an actor blocks on the answer of an ask while handling a message.
+    pub fn blocking_ask_policy(&self) -> BlockingAskPolicy {
+        *self.inner.blocking_ask_policy.read().unwrap()
+    }
+
+    /// Number of consumer threads currently compensating for threads blocked on an ask.
+    pub fn compensating_threads(&self) -> usize {
+        self.inner.compensating_threads.load(Ordering::SeqCst)
+    }
+
+    /// Spawns a consumer thread compensating for a thread blocked on an ask.
+    pub fn begin_compensation(&self) {
+        self.inner.compensating_threads.fetch_add(1, Ordering::SeqCst);
+        self.spawn_thread();
+    }
+
+    /// Terminates a consumer thread spawned with `begin_compensation`.
+    pub fn end_compensation(&self) {
+        self.inner.compensating_threads.fetch_sub(1, Ordering::SeqCst);
+        self.terminate_threads(1);
+    }
+
+    /// Gives the watchdog of the actor system.
+    pub fn watchdog(&self) -> Watchdog {
+        self.inner.watchdog.clone()
+    }
+
+    /// Starts the watchdog, which reports the actors handling a message for longer than
+    /// `threshold`, and if `replace_stuck_threads` is set spawns a consumer thread to replace each
+    /// thread blocked that way.
+    ///
+    /// Calling it again changes the configuration of the watchdog.
+    pub fn start_watchdog(&self, threshold: Duration, replace_stuck_threads: bool) {
+        self.inner.watchdog.start(self.clone(), threshold, replace_stuck_threads);
+    }
+
+    /// Registers the extension of type `E` of the actor system, creating it with `E::create` if
+    /// it is not registered yet. Returns the registered extension.
+    ///
+    /// There is a single instance of each extension per actor system, they are shut down when the
+    /// actor system is, in reverse registration order.
+    pub fn register_extension<E: Extension>(&self) -> Arc<E> {
+        self.inner.extensions.get_or_create::<E>(self)
+    }
+
+    /// Gives the extension of type `E` of the actor system, if it is registered.
+    pub fn extension<E: Extension>(&self) -> Option<Arc<E>> {
+        self.inner.extensions.get::<E>()
+    }
+
+    /// Gives the event stream of the actor system.
+    pub fn event_stream(&self) -> EventStream {
+        self.inner.event_stream.clone()
+    }
+
+    /// Sets whether the messages not handled by the actors are logged at debug level, they are
+    /// always published to the event stream as `UnhandledMessage`s. They are not logged by default.
+    pub fn set_log_unhandled(&self, log_unhandled: bool) {
+        self.inner.log_unhandled.store(log_unhandled, Ordering::SeqCst);
+    }
+
+    /// Whether the messages not handled by the actors are logged.
+    pub fn log_unhandled(&self) -> bool {
+        self.inner.log_unhandled.load(Ordering::SeqCst)
+    }
+
+    /// Gives the metrics registry of the actor system.
+    pub fn metrics(&self) -> Metrics {
+        self.inner.metrics.clone()
+    }
+
+    /// Takes a snapshot of the actors under `/user`, `/system` and `/temp`.
+    pub fn snapshot(&self) -> SystemSnapshot {
+        let user_actor = self.inner.user_actor.read().unwrap().clone();
+        let system_actor = self.inner.system_actor.read().unwrap().clone();
+        let temp_actor = self.inner.temp_actor.read().unwrap().clone();
+        SystemSnapshot {
+            name: self.inner.name.clone(),
+            user: user_actor.and_then(|actor| actor.snapshot()),
+            system: system_actor.and_then(|actor| actor.snapshot()),
+            temp: temp_actor.and_then(|actor| actor.snapshot()),
+        }
+    }
+
+    /// Gives the scheduler of the actor system.
+    pub fn scheduler(&self) -> Scheduler {
+        self.inner.scheduler.clone()
+    }
+
+    /// Gives the ActorRef of the dead letters actor.
+    pub fn dead_letters(&self) -> ActorRef {
+        match self.inner.dead_letters.read().unwrap().as_ref() {
+            None => panic!("The dead letters actor is not initialized."),
+            Some(dead_letters) => dead_letters.clone(),
+        }
+    }
+
+    /// Creates an `Inbox`, which can send messages to the actors and receive their replies from
+    /// outside of the actor system.
+    pub fn inbox(&self) -> Inbox {
+        Inbox::new(self)
+    }
+
+    /// Sends a message to `to` as if it was sent by `sender`, `to` then replies to `sender`.
+    ///
+    /// This can be used from outside of the actors.
+    pub fn tell_with_sender<MessageTo: Message>(&self, to: ActorRef, message: MessageTo, sender: ActorRef) {
+        send_message(to, message, sender);
+    }
+
+    /// Sends a message to `to` from outside of the actors, without a sender.
+    ///
+    /// The sender seen by `to` is the dead letters actor, so its replies go to the dead letters.
+    pub fn tell_no_sender<MessageTo: Message>(&self, to: ActorRef, message: MessageTo) {
+        send_message(to, message, self.dead_letters());
+    }
+
+    /// Sends a message that could not be delivered to `recipient` to the dead letters actor.
+    pub fn dead_letter(&self, message: InnerMessage, sender: ActorRef, recipient: ActorRef) {
+        let message = match message {
+            InnerMessage::Message(message) => message,
+            InnerMessage::Control(message) => Box::new(message),
+        };
+        self.inner.metrics.record_dead_letter(&recipient.path());
+        let dead_letters = self.inner.dead_letters.read().unwrap().clone();
+        match dead_letters {
+            // If the dead letters actor is itself stopped we do not want to loop forever.
+            Some(ref dead_letters) if *dead_letters != recipient => {
+                let dead_letter = DeadLetter {
+                    message: message,
+                    sender: sender.clone(),
+                    recipient: recipient,
+                };
+                register_message_type::<DeadLetter>();
+                dead_letters.receive(InnerMessage::Message(Box::new(dead_letter)), sender);
+            }
+            _ => println!("A message was send to a ref to a stopped actor"),
+        }
+    }
 }
 
 impl Clone for ActorSystem {
@@ -179,8 +554,26 @@ impl Clone for ActorSystem {
     }
 }
 
+/// Run queue of a deterministic actor system.
+struct DeterministicDispatcher {
+    seed: u64,
+    rng: Mutex<StdRng>,
+    run_queue: Mutex<Vec<ActorRef>>,
+}
+
+impl DeterministicDispatcher {
+    fn new(seed: u64) -> DeterministicDispatcher {
+        let seed_parts = [seed as usize, (seed >> 32) as usize];
+        DeterministicDispatcher {
+            seed: seed,
+            rng: Mutex::new(StdRng::from_seed(&seed_parts[..])),
+            run_queue: Mutex::new(Vec::new()),
+        }
+    }
+}
+
 struct InnerActorSystem {
-    _name: String,
+    name: String,
     // Communication channels to the co,sumer threads.
     consumer_threads_sender: Mutex<Sender<()>>,
     consumer_threads_receiver: Arc<Mutex<Receiver<()>>>,
@@ -192,16 +585,39 @@ struct InnerActorSystem {
     cthulhu: RwLock<Option<ActorRef >>,
     user_actor: RwLock<Option<ActorRef>>,
     system_actor: RwLock<Option<ActorRef>>,
+    // Cells of the root actors, used to create their children directly from any thread. These
+    // only keep weak references, the actors are owned by `user_actor` and `system_actor`.
+    user_actor_cell: RwLock<Option<ActorCell>>,
+    system_actor_cell: RwLock<Option<ActorCell>>,
+    // Root of the temporary actors.
+    temp_actor: RwLock<Option<ActorRef>>,
+    temp_actor_cell: RwLock<Option<ActorCell>>,
     // ActorRef to the name resolver.
     name_resolver: RwLock<Option<ActorRef>>,
+    // ActorRef to the dead letters actor.
+    dead_letters: RwLock<Option<ActorRef>>,
+    scheduler: Scheduler,
+    metrics: Metrics,
+    watchdog: Watchdog,
+    event_stream: EventStream,
+    log_unhandled: AtomicBool,
+    extensions: Extensions,
+    blocking_ask_policy: RwLock<BlockingAskPolicy>,
+    compensating_threads: AtomicUsize,
+    // Set for deterministic actor systems, the actors are then queued here instead of being sent
+    // to the consumer threads.
+    deterministic: Option<DeterministicDispatcher>,
+    // Set for actor systems running on a tokio runtime.
+    #[cfg(feature = "tokio")]
+    tokio: Option<TokioDispatcher>,
 }
 
 impl InnerActorSystem {
-    fn new(name: String) -> InnerActorSystem {
+    fn new(name: String, deterministic: Option<DeterministicDispatcher>) -> InnerActorSystem {
         let (tx_queue, rx_queue) = channel();
         let (tx_thread, rx_thread) = channel();
         InnerActorSystem {
-            _name: name,
+            name: name,
             consumer_threads_sender: Mutex::new(tx_thread),
             consumer_threads_receiver: Arc::new(Mutex::new(rx_thread)),
             n_threads: Mutex::new(0u32),
@@ -210,52 +626,86 @@ impl InnerActorSystem {
             cthulhu: RwLock::new(None),
             user_actor: RwLock::new(None),
             system_actor: RwLock::new(None),
+            user_actor_cell: RwLock::new(None),
+            system_actor_cell: RwLock::new(None),
+            temp_actor: RwLock::new(None),
+            temp_actor_cell: RwLock::new(None),
             name_resolver: RwLock::new(None),
+            dead_letters: RwLock::new(None),
+            scheduler: if deterministic.is_some() {
+                Scheduler::new_virtual()
+            } else {
+                Scheduler::new()
+            },
+            metrics: Metrics::new(),
+            watchdog: Watchdog::new(),
+            event_stream: EventStream::new(),
+            log_unhandled: AtomicBool::new(false),
+            extensions: Extensions::new(),
+            blocking_ask_policy: RwLock::new(BlockingAskPolicy::default()),
+            compensating_threads: AtomicUsize::new(0),
+            deterministic: deterministic,
+            #[cfg(feature = "tokio")]
+            tokio: None,
         }
     }
 
     /// Spawns an Actor for the user with the given ActorFactory.
     ///
     /// This will be part of the user cator hierarchy.
-    fn actor_of(&self, props: Arc<ActorFactory>, name: String) -> ActorRef {
-        // Not having the user actor in a Mutex is ok because the actor_of function already has
-        // mutual exclusion, so we are in the clear.
-        match self.user_actor.read().unwrap().clone() {
-            Some(user_actor) => {
-                let future = user_actor.ask((props, name));
-                let answer = future.await().unwrap();
-                *Box::<Any>::downcast::<ActorRef>(answer).unwrap()
-            },
+    fn actor_of(&self, props: Arc<ActorFactory>, name: String) -> Result<ActorRef, ActorCreationError> {
+        // The actor is created right away in the children of the user actor, this does not need
+        // the user actor to handle a message so it never blocks.
+        match self.user_actor_cell.read().unwrap().clone() {
+            Some(user_actor) => user_actor.actor_of(props, name),
             None => panic!("The user actor is not initialised"),
         }
     }
 
-    fn system_actor_of(&self, props: Arc<ActorFactory>, name: String) -> ActorRef {
-        // Not having the user actor in a Mutex is ok because the actor_of function already has
-        // mutual exclusion, so we are in the clear.
-        match self.system_actor.read().unwrap().clone() {
-            Some(system_actor) => {
-                let future = system_actor.ask((props, name));
-                let answer = future.await().unwrap();
-                *Box::<Any>::downcast::<ActorRef>(answer).unwrap()
-            },
+    /// Spawns an Actor for the user with the given ActorFactory and a generated name.
+    fn actor_of_anonymous(&self, props: Arc<ActorFactory>) -> ActorRef {
+        match self.user_actor_cell.read().unwrap().clone() {
+            Some(user_actor) => user_actor.actor_of_anonymous(props),
             None => panic!("The user actor is not initialised"),
         }
     }
 
+    fn system_actor_of(&self, props: Arc<ActorFactory>, name: String) -> Result<ActorRef, ActorCreationError> {
+        match self.system_actor_cell.read().unwrap().clone() {
+            Some(system_actor) => system_actor.actor_of(props, name),
+            None => panic!("The system actor is not initialised"),
+        }
+    }
+
     /// Shuts the actor system down.
     fn shutdown(&self) {
         // We have to get this out of the mutex, because terminate_threads would deadlock on
         // n_thread.
         let n = {*self.n_threads.lock().unwrap()};
+        // The extensions are shut down first, so that they can still talk to their actors.
+        self.extensions.shutdown();
         self.terminate_threads(n);
+        self.scheduler.shutdown();
+        self.watchdog.stop();
+        self.event_stream.clear();
+        if let Some(ref dispatcher) = self.deterministic {
+            dispatcher.run_queue.lock().unwrap().clear();
+        }
+        *self.user_actor_cell.write().unwrap() = None;
+        *self.system_actor_cell.write().unwrap() = None;
+        *self.temp_actor_cell.write().unwrap() = None;
         *self.user_actor.write().unwrap() = None;
         *self.system_actor.write().unwrap() = None;
+        *self.temp_actor.write().unwrap() = None;
         *self.cthulhu.write().unwrap() = None;
     }
 
     /// Enqueues the given ActorRef in the list of ActorRef with messages to be handled.
     fn enqueue_actor(&self, actor_ref: ActorRef) {
+        if let Some(ref dispatcher) = self.deterministic {
+            dispatcher.run_queue.lock().unwrap().push(actor_ref);
+            return;
+        }
         match self.actors_queue_sender.lock().unwrap().send(actor_ref) {
             Ok(_) => return,
             Err(_) => {
@@ -267,8 +717,13 @@ impl InnerActorSystem {
 
     /// Kills a consumer thread.
     fn terminate_thread(&self) {
+        let mut n_threads = self.n_threads.lock().unwrap();
+        // Threads can be terminated after the shutdown, by the end of a compensation for example.
+        if *n_threads == 0 {
+            return;
+        }
         let _ = self.consumer_threads_sender.lock().unwrap().send(());
-        *self.n_threads.lock().unwrap() -= 1;
+        *n_threads -= 1;
     }
 
     /// Kills n consumer threads.
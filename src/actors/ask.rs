@@ -0,0 +1,127 @@
+use std::any::Any;
+use std::error::Error;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use actors::{Actor, ActorCell, ActorContext, ActorRef, Cancellable, Message};
+
+/// Failure given to the `map_reply` closure of `ActorContext::ask_with` when no reply was
+/// received in time.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AskTimeout {
+    /// The asked actor.
+    pub target: ActorRef,
+    /// The timeout of the ask.
+    pub timeout: Duration,
+}
+
+impl fmt::Display for AskTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} did not reply within {:?}", self.target, self.timeout)
+    }
+}
+
+impl Error for AskTimeout {
+    fn description(&self) -> &str {
+        "ask timeout"
+    }
+}
+
+/// Closure converting the reply to an `ask_with` into the message delivered to the asker.
+pub type MapReply<R> = Arc<Fn(Result<Box<Any>, AskTimeout>) -> R + Send + Sync>;
+
+/// Arguments of an `AskActor`.
+pub struct AskArgs<M: Message, R: Message> {
+    /// The asking actor.
+    pub asker: ActorRef,
+    /// The asked actor.
+    pub target: ActorRef,
+    /// The request.
+    pub message: M,
+    /// How long to wait for the reply.
+    pub timeout: Duration,
+    /// Conversion of the reply.
+    pub map_reply: MapReply<R>,
+}
+
+impl<M: Message, R: Message> Clone for AskArgs<M, R> {
+    fn clone(&self) -> AskArgs<M, R> {
+        AskArgs {
+            asker: self.asker.clone(),
+            target: self.target.clone(),
+            message: self.message.clone(),
+            timeout: self.timeout,
+            map_reply: self.map_reply.clone(),
+        }
+    }
+}
+
+// Sent by the AskActor to itself when the timeout is reached.
+#[derive(Clone, Copy)]
+struct AskTimeoutTick;
+
+/// Temporary actor sending a request and delivering the converted reply (or timeout) to the
+/// asker, it then stops itself.
+///
+/// It lives under `/temp`, so the asked actor sees a regular sender with a resolvable path. It is
+/// also the sender of the converted reply.
+pub struct AskActor<M: Message, R: Message> {
+    args: AskArgs<M, R>,
+    // Timeout scheduled when the request is sent, and whether the ask is over.
+    state: Mutex<(Option<Cancellable>, bool)>,
+    _reply: PhantomData<R>,
+}
+
+impl<M: Message, R: Message> AskActor<M, R> {
+    /// Creates the actor.
+    pub fn new(args: AskArgs<M, R>) -> AskActor<M, R> {
+        AskActor {
+            args: args,
+            state: Mutex::new((None, false)),
+            _reply: PhantomData,
+        }
+    }
+
+    fn complete(&self, reply: Result<Box<Any>, AskTimeout>, context: &ActorCell) {
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.1 {
+                return;
+            }
+            state.1 = true;
+            if let Some(timeout) = state.0.take() {
+                timeout.cancel();
+            }
+        }
+        let message = (self.args.map_reply)(reply);
+        context.tell(self.args.asker.clone(), message);
+        context.kill_me();
+    }
+}
+
+impl<M: Message, R: Message> Actor for AskActor<M, R> {
+    fn pre_start(&self, context: ActorCell) {
+        context.tell(self.args.target.clone(), self.args.message.clone());
+        let timeout = context.schedule_once(self.args.timeout, context.actor_ref(), AskTimeoutTick);
+        self.state.lock().unwrap().0 = Some(timeout);
+    }
+
+    fn post_restart(&self, context: ActorCell) {
+        // The conversion of the reply panicked, there is nothing left to do.
+        context.kill_me();
+    }
+
+    fn receive(&self, message: Box<Any>, context: ActorCell) {
+        if message.is::<AskTimeoutTick>() {
+            let timeout = AskTimeout {
+                target: self.args.target.clone(),
+                timeout: self.args.timeout,
+            };
+            self.complete(Err(timeout), &context);
+        } else {
+            self.complete(Ok(message), &context);
+        }
+    }
+}
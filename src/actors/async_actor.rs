@@ -0,0 +1,148 @@
+extern crate eventual;
+
+use std::any::Any;
+use std::future::Future as StdFuture;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use self::eventual::{Async, AsyncError, AsyncResult};
+
+use actors::{Actor, ActorCell, ActorRef, Arguments, SystemMessage};
+use actors::props::{ActorFactory, ActorInstance};
+
+/// Future returned by the handlers of `AsyncActor`s.
+pub type BoxFuture = Pin<Box<StdFuture<Output = ()> + Send>>;
+
+struct EventualState<T: Send + 'static, E: Send + 'static> {
+    result: Option<AsyncResult<T, E>>,
+    waker: Option<Waker>,
+}
+
+/// A `std::future::Future` giving the outcome of an eventual `Async` value, so that it can be
+/// awaited in async Rust.
+///
+/// It is returned by `ActorRef::ask_async` and `ActorContext::identify_actor_async`.
+pub struct EventualFuture<T: Send + 'static, E: Send + 'static> {
+    state: Arc<Mutex<EventualState<T, E>>>,
+}
+
+impl<T: Send + 'static, E: Send + 'static> EventualFuture<T, E> {
+    /// Wraps `future`.
+    pub fn new<A: Async<Value = T, Error = E>>(future: A) -> EventualFuture<T, E> {
+        let state = Arc::new(Mutex::new(EventualState {
+            result: None,
+            waker: None,
+        }));
+        let completed = state.clone();
+        future.receive(move |result| {
+            let waker = {
+                let mut state = completed.lock().unwrap();
+                state.result = Some(result);
+                state.waker.take()
+            };
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        });
+        EventualFuture { state: state }
+    }
+}
+
+impl<T: Send + 'static, E: Send + 'static> StdFuture for EventualFuture<T, E> {
+    type Output = Result<T, AsyncError<E>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let mut state = self.state.lock().unwrap();
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Trait to implement for actors whose handler is asynchronous.
+///
+/// The future returned by `receive` is run by the actor cell, the actor handles no other message
+/// until it is completed, so the messages are still handled one at a time and in order. No
+/// consumer thread is blocked meanwhile: the future is polled again when it is woken.
+///
+/// Such actors are created with `AsyncProps`.
+pub trait AsyncActor: Send + Sync + 'static {
+    /// Handles a message, the actor handles the next one once the returned future is completed.
+    fn receive(&self, message: Box<Any + Send>, context: ActorCell) -> BoxFuture;
+
+    /// Method called before the Actor is started.
+    fn pre_start(&self, _context: ActorCell) {}
+
+    /// Method called after the Actor is stopped.
+    fn post_stop(&self) {}
+}
+
+/// Runs an `AsyncActor` as an `Actor`.
+struct AsyncActorAdapter<A: AsyncActor> {
+    actor: A,
+}
+
+impl<A: AsyncActor> Actor for AsyncActorAdapter<A> {
+    fn receive(&self, _message: Box<Any>, _context: ActorCell) {
+        panic!("The messages of an AsyncActor are handled with receive_async.");
+    }
+
+    fn receive_async(&self, message: Box<Any + Send>, context: ActorCell) -> Option<BoxFuture> {
+        Some(self.actor.receive(message, context))
+    }
+
+    fn pre_start(&self, context: ActorCell) {
+        self.actor.pre_start(context);
+    }
+
+    fn post_stop(&self) {
+        self.actor.post_stop();
+    }
+}
+
+/// Factory for AsyncActors, it is the equivalent of `Props`.
+pub struct AsyncProps<Args: Arguments, A: AsyncActor> {
+    creator: Arc<Fn(Args) -> A + Sync + Send>,
+    args: Args,
+}
+
+impl<Args: Arguments, A: AsyncActor> AsyncProps<Args, A> {
+    /// Creates an `AsyncProps` which is a factory for `A` with the `creator` function and `args`
+    /// args.
+    pub fn new(creator: Arc<Fn(Args) -> A + Sync + Send>, args: Args) -> Arc<ActorFactory> {
+        Arc::new(AsyncProps::<Args, A> {
+            creator: creator,
+            args: args,
+        })
+    }
+}
+
+impl<Args: Arguments, A: AsyncActor> ActorFactory for AsyncProps<Args, A> {
+    fn create(&self) -> ActorInstance {
+        let actor = AsyncActorAdapter { actor: (self.creator)(self.args.clone()) };
+        ActorInstance::Shared(Arc::new(actor))
+    }
+}
+
+/// Waker of the pending future of an actor, it makes the actor poll it again.
+pub struct ResumeWaker {
+    actor_ref: ActorRef,
+}
+
+impl ResumeWaker {
+    /// Creates a waker resuming the actor of `actor_ref`.
+    pub fn waker(actor_ref: ActorRef) -> Waker {
+        Waker::from(Arc::new(ResumeWaker { actor_ref: actor_ref }))
+    }
+}
+
+impl ::std::task::Wake for ResumeWaker {
+    fn wake(self: Arc<Self>) {
+        self.actor_ref.receive_system_message(SystemMessage::Resume);
+    }
+}
@@ -0,0 +1,195 @@
+extern crate eventual;
+extern crate log;
+
+use self::eventual::{Async, AsyncResult, Cancel, Future, Receipt};
+use self::log::warn;
+
+use std::cell::RefCell;
+use std::sync::Arc;
+
+use actors::{ActorPath, ActorSystem};
+
+/// What to do when an actor waits for the answer of an ask while handling a message.
+///
+/// Waiting for the answer of an `ask` blocks the consumer thread, if the asked actor needs that
+/// same thread to answer (with a single consumer thread for example) the actor system deadlocks.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BlockingAskPolicy {
+    /// A consumer thread is spawned to compensate for the blocked one, it is terminated once the
+    /// answer is received. This is the default.
+    Compensate,
+    /// The waiting actor panics with an explanation, it is then restarted as usual.
+    FailFast,
+    /// Nothing is done, the blocking waits are only logged.
+    Ignore,
+}
+
+impl Default for BlockingAskPolicy {
+    fn default() -> BlockingAskPolicy {
+        BlockingAskPolicy::Compensate
+    }
+}
+
+thread_local! {
+    // The actor system and the path of the actor whose message is being handled by this thread.
+    static HANDLING: RefCell<Option<(ActorSystem, Arc<ActorPath>)>> = RefCell::new(None);
+}
+
+/// Marks the current thread as handling a message of the actor at `path` until it is dropped.
+pub struct HandlingMarker {
+    previous: Option<(ActorSystem, Arc<ActorPath>)>,
+}
+
+impl HandlingMarker {
+    /// Marks the current thread.
+    pub fn enter(actor_system: ActorSystem, path: Arc<ActorPath>) -> HandlingMarker {
+        let previous = HANDLING.with(|handling| handling.borrow_mut().replace((actor_system, path)));
+        HandlingMarker { previous: previous }
+    }
+}
+
+impl Drop for HandlingMarker {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        HANDLING.with(|handling| *handling.borrow_mut() = previous);
+    }
+}
+
+/// A consumer thread spawned to compensate for a blocked one, it is terminated when this is
+/// dropped.
+struct Compensation {
+    actor_system: ActorSystem,
+}
+
+impl Drop for Compensation {
+    fn drop(&mut self) {
+        self.actor_system.end_compensation();
+    }
+}
+
+/// Applies the blocking ask policy if the current thread is handling a message, called before
+/// blocking on the answer of `target`.
+///
+/// The returned compensation must be dropped once the answer is received.
+fn on_blocking_wait(target: &ActorPath) -> Option<Compensation> {
+    let handling = HANDLING.with(|handling| handling.borrow().clone());
+    let (actor_system, path) = match handling {
+        Some(handling) => handling,
+        None => return None,
+    };
+    match actor_system.blocking_ask_policy() {
+        BlockingAskPolicy::Ignore => {
+            warn!("Actor {} waits for an answer of {} while handling a message, this may deadlock",
+                  path.logical_path(),
+                  target.logical_path());
+            None
+        }
+        BlockingAskPolicy::FailFast => {
+            panic!("Actor {} waits for an answer of {} while handling a message, this may deadlock \
+                    the consumer threads. Send a message and handle the answer in `receive` instead.",
+                   path.logical_path(),
+                   target.logical_path())
+        }
+        BlockingAskPolicy::Compensate => {
+            // The turns are run on the blocking threads of the runtime, which grow as needed.
+            if actor_system.is_on_tokio() {
+                return None;
+            }
+            if actor_system.is_deterministic() {
+                warn!("Actor {} waits for an answer of {} while handling a message, this blocks a \
+                       deterministic actor system",
+                      path.logical_path(),
+                      target.logical_path());
+                return None;
+            }
+            warn!("Actor {} waits for an answer of {} while handling a message, spawning a \
+                   compensating consumer thread",
+                  path.logical_path(),
+                  target.logical_path());
+            actor_system.begin_compensation();
+            Some(Compensation { actor_system: actor_system })
+        }
+    }
+}
+
+/// The answer to an `ask`.
+///
+/// This is a regular `Async` value, except that blocking on it with `await` while handling a
+/// message applies the `BlockingAskPolicy` of the actor system. Nothing is done when the answer
+/// has already been received, or when it is consumed with callbacks.
+pub struct AskFuture<T: Send + 'static> {
+    future: Future<T, &'static str>,
+    target: Arc<ActorPath>,
+}
+
+impl<T: Send + 'static> AskFuture<T> {
+    /// Creates an AskFuture for an answer of the actor at `target`.
+    pub fn new(future: Future<T, &'static str>, target: Arc<ActorPath>) -> AskFuture<T> {
+        AskFuture {
+            future: future,
+            target: target,
+        }
+    }
+
+    /// Transforms the answer once it is received, keeping the blocking ask policy for `await`.
+    pub fn map_answer<U, F>(self, f: F) -> AskFuture<U>
+        where U: Send + 'static,
+              F: FnOnce(T) -> U + Send + 'static
+    {
+        AskFuture {
+            future: self.future.map(f),
+            target: self.target,
+        }
+    }
+}
+
+impl<T: Send + 'static> Async for AskFuture<T> {
+    type Value = T;
+    type Error = &'static str;
+    type Cancel = AskReceipt<T>;
+
+    fn is_ready(&self) -> bool {
+        self.future.is_ready()
+    }
+
+    fn is_err(&self) -> bool {
+        self.future.is_err()
+    }
+
+    fn poll(self) -> Result<AsyncResult<T, &'static str>, AskFuture<T>> {
+        let target = self.target;
+        self.future.poll().map_err(|future| AskFuture::new(future, target))
+    }
+
+    fn ready<F>(self, f: F) -> AskReceipt<T>
+        where F: FnOnce(AskFuture<T>) + Send + 'static
+    {
+        let target = self.target.clone();
+        let receipt = self.future.ready(move |future| f(AskFuture::new(future, target)));
+        AskReceipt {
+            receipt: receipt,
+            target: self.target,
+        }
+    }
+
+    fn await(self) -> AsyncResult<T, &'static str> {
+        if self.future.is_ready() {
+            return self.future.await();
+        }
+        let _compensation = on_blocking_wait(&self.target);
+        self.future.await()
+    }
+}
+
+/// Cancels the callback given to `AskFuture::ready`.
+pub struct AskReceipt<T: Send + 'static> {
+    receipt: Receipt<Future<T, &'static str>>,
+    target: Arc<ActorPath>,
+}
+
+impl<T: Send + 'static> Cancel<AskFuture<T>> for AskReceipt<T> {
+    fn cancel(self) -> Option<AskFuture<T>> {
+        let target = self.target;
+        self.receipt.cancel().map(|future| AskFuture::new(future, target))
+    }
+}
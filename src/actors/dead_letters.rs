@@ -0,0 +1,45 @@
+extern crate log;
+
+use std::any::Any;
+
+use self::log::info;
+
+use actors::{Actor, ActorCell, ActorContext, ActorRef};
+
+/// A message that could not be delivered to its recipient.
+///
+/// This happens for example when a message is sent to an actor that has been stopped (even if a
+/// new actor has since been created at the same path).
+pub struct DeadLetter {
+    /// The undelivered message, this is a `ControlMessage` if a control message was sent.
+    pub message: Box<Any + Send>,
+    /// Sender of the message.
+    pub sender: ActorRef,
+    /// Intended recipient of the message.
+    pub recipient: ActorRef,
+}
+
+/// Actor receiving all the messages that could not be delivered.
+///
+/// It is also the sender of the messages sent with `ActorSystem::tell_no_sender`, so the replies to
+/// these messages end up here.
+///
+/// For now it only logs them.
+pub struct DeadLetters;
+
+impl Actor for DeadLetters {
+    fn receive(&self, message: Box<Any>, context: ActorCell) {
+        match Box::<Any>::downcast::<DeadLetter>(message) {
+            Ok(dead_letter) => {
+                info!("Dead letter from {} to {}", dead_letter.sender, dead_letter.recipient)
+            }
+            Err(_) => info!("Dead letter from {} to no sender", context.sender()),
+        }
+    }
+}
+
+impl DeadLetters {
+    pub fn new(_dummy: ()) -> DeadLetters {
+        DeadLetters
+    }
+}
@@ -0,0 +1,113 @@
+extern crate robots_derive;
+
+use std::any::Any;
+
+pub use self::robots_derive::Dispatch;
+
+/// Conversion of the `Box<Any>` given to `Actor::receive` into a typed message.
+///
+/// It is usually derived for an enum of messages with `#[derive(Dispatch)]`, the derive macro of
+/// the `robots_derive` crate being re-exported with the trait. `receive` can then match the enum
+/// exhaustively:
+///
+/// ```ignore
+/// #[derive(Clone, Dispatch)]
+/// enum CounterMessage {
+///     #[dispatch_from]
+///     Add(u32),
+///     Get,
+/// }
+///
+/// fn receive(&self, message: Box<Any>, context: ActorCell) {
+///     match CounterMessage::dispatch(message) {
+///         Ok(CounterMessage::Add(n)) => *self.count.lock().unwrap() += n,
+///         Ok(CounterMessage::Get) => context.tell(context.sender(), *self.count.lock().unwrap()),
+///         Err(message) => self.unhandled(message, context),
+///     }
+/// }
+/// ```
+///
+/// The derived implementation accepts the enum itself, and the fields of the single field variants
+/// marked with `#[dispatch_from]` (a `u32` sent to the counter above is an `Add`).
+pub trait Dispatch: Sized {
+    /// Converts `message`, or gives it back if it is not one of the accepted types.
+    fn dispatch(message: Box<Any>) -> Result<Self, Box<Any>>;
+}
+
+/// Matches a `Box<Any>` message against types and patterns, the messages matched by no arm are
+/// given to `Actor::unhandled`.
+///
+/// Each arm is a type, `as`, and a pattern for the values of this type with an optional guard.
+/// When a message has the type of an arm but does not match its pattern, the next arms are tried.
+///
+/// The macro can be imported with `use robots::receive;` as well as with `#[macro_use]`.
+///
+/// ```ignore
+/// fn receive(&self, message: Box<Any>, context: ActorCell) {
+///     receive!(self, message, context, {
+///         u32 as 0 => println!("zero"),
+///         u32 as n if n % 2 == 0 => context.tell(context.sender(), n / 2),
+///         String as name => {
+///             println!("Hello {}", name);
+///         }
+///         Command as Command::Stop => context.kill_me(),
+///     });
+/// }
+/// ```
+#[macro_export]
+macro_rules! receive {
+    ($actor:expr, $message:expr, $context:expr, { $($arms:tt)* }) => {
+        $crate::receive!(@arms $actor, $context, $message, $($arms)*)
+    };
+    (@arms $actor:expr, $context:expr, $message:expr, , $($rest:tt)*) => {
+        $crate::receive!(@arms $actor, $context, $message, $($rest)*)
+    };
+    (@arms $actor:expr, $context:expr, $message:expr,) => {
+        $actor.unhandled($message, $context)
+    };
+    (@arms $actor:expr, $context:expr, $message:expr,
+     $t:ty as $p:pat if $guard:expr => $body:block $($rest:tt)*) => {
+        $crate::receive!(@arm $actor, $context, $message, $t, $p, $guard, $body, $($rest)*)
+    };
+    (@arms $actor:expr, $context:expr, $message:expr,
+     $t:ty as $p:pat => $body:block $($rest:tt)*) => {
+        $crate::receive!(@arm $actor, $context, $message, $t, $p, true, $body, $($rest)*)
+    };
+    (@arms $actor:expr, $context:expr, $message:expr,
+     $t:ty as $p:pat if $guard:expr => $body:expr, $($rest:tt)*) => {
+        $crate::receive!(@arm $actor, $context, $message, $t, $p, $guard, $body, $($rest)*)
+    };
+    (@arms $actor:expr, $context:expr, $message:expr,
+     $t:ty as $p:pat => $body:expr, $($rest:tt)*) => {
+        $crate::receive!(@arm $actor, $context, $message, $t, $p, true, $body, $($rest)*)
+    };
+    (@arms $actor:expr, $context:expr, $message:expr,
+     $t:ty as $p:pat if $guard:expr => $body:expr) => {
+        $crate::receive!(@arm $actor, $context, $message, $t, $p, $guard, $body,)
+    };
+    (@arms $actor:expr, $context:expr, $message:expr,
+     $t:ty as $p:pat => $body:expr) => {
+        $crate::receive!(@arm $actor, $context, $message, $t, $p, true, $body,)
+    };
+    (@arm $actor:expr, $context:expr, $message:expr, $t:ty, $p:pat, $guard:expr, $body:expr,
+     $($rest:tt)*) => {
+        match ::std::boxed::Box::<::std::any::Any>::downcast::<$t>($message) {
+            ::std::result::Result::Ok(message) => {
+                match *message {
+                    $p if $guard => {
+                        $body;
+                    }
+                    #[allow(unreachable_patterns)]
+                    message => {
+                        let message: ::std::boxed::Box<::std::any::Any> =
+                            ::std::boxed::Box::new(message);
+                        $crate::receive!(@arms $actor, $context, message, $($rest)*)
+                    }
+                }
+            }
+            ::std::result::Result::Err(message) => {
+                $crate::receive!(@arms $actor, $context, message, $($rest)*)
+            }
+        }
+    };
+}
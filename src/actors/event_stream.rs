@@ -0,0 +1,98 @@
+extern crate log;
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use self::log::debug;
+
+use actors::{ActorCell, ActorContext, ActorRef, InnerMessage, Message};
+use actors::watchdog::{message_type_name, register_message_type};
+
+/// Event published when an actor did not handle a message, see `Actor::unhandled`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UnhandledMessage {
+    /// Name of the type of the message, `<unknown>` if it was never sent with a generic method.
+    pub type_name: &'static str,
+    /// Sender of the message.
+    pub sender: ActorRef,
+    /// Actor which did not handle it.
+    pub recipient: ActorRef,
+}
+
+/// Event bus of an actor system, given by `ActorSystem::event_stream`.
+///
+/// Actors subscribe to a type of event and receive all the events of that type published
+/// afterwards, such as the `UnhandledMessage`s.
+#[derive(Clone)]
+pub struct EventStream {
+    subscribers: Arc<RwLock<HashMap<TypeId, Vec<ActorRef>>>>,
+}
+
+impl EventStream {
+    /// Creates an event stream without subscribers.
+    pub fn new() -> EventStream {
+        EventStream { subscribers: Arc::new(RwLock::new(HashMap::new())) }
+    }
+
+    /// Makes `subscriber` receive the events of type `T`.
+    pub fn subscribe<T: Message>(&self, subscriber: ActorRef) {
+        let mut subscribers = self.subscribers.write().unwrap();
+        let subscribers = subscribers.entry(TypeId::of::<T>()).or_insert_with(Vec::new);
+        if !subscribers.contains(&subscriber) {
+            subscribers.push(subscriber);
+        }
+    }
+
+    /// Stops sending the events of type `T` to `subscriber`.
+    pub fn unsubscribe<T: Message>(&self, subscriber: &ActorRef) {
+        if let Some(subscribers) = self.subscribers.write().unwrap().get_mut(&TypeId::of::<T>()) {
+            subscribers.retain(|s| s != subscriber);
+        }
+    }
+
+    /// Sends `event` to the subscribers of its type, with `sender` as sender.
+    ///
+    /// Returns the number of subscribers it was sent to.
+    pub fn publish<T: Message>(&self, event: T, sender: ActorRef) -> usize {
+        let subscribers = match self.subscribers.read().unwrap().get(&TypeId::of::<T>()) {
+            Some(subscribers) => subscribers.clone(),
+            None => return 0,
+        };
+        register_message_type::<T>();
+        for subscriber in &subscribers {
+            let event: Box<Any + Send> = Box::new(event.clone());
+            subscriber.receive(InnerMessage::Message(event), sender.clone());
+        }
+        subscribers.len()
+    }
+
+    /// Removes all the subscribers.
+    pub fn clear(&self) {
+        self.subscribers.write().unwrap().clear();
+    }
+}
+
+impl Default for EventStream {
+    fn default() -> EventStream {
+        EventStream::new()
+    }
+}
+
+/// Publishes the `UnhandledMessage` event of `message`, which was not handled by the actor of
+/// `context`, and logs it at debug level if the actor system is configured so.
+///
+/// This is what `Actor::unhandled` does by default. When it is called outside of the handling of
+/// a message, the dead letters are given as sender.
+pub fn publish_unhandled(message: &Any, context: &ActorCell) {
+    let system = context.system();
+    let event = UnhandledMessage {
+        type_name: message_type_name(message),
+        sender: context.current_sender().unwrap_or_else(|| system.dead_letters()),
+        recipient: context.actor_ref(),
+    };
+    if system.log_unhandled() {
+        debug!("{} did not handle a {} from {}", event.recipient, event.type_name, event.sender);
+    }
+    system.event_stream().publish(event, context.actor_ref());
+}
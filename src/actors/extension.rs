@@ -0,0 +1,157 @@
+use std::any::{type_name, Any, TypeId};
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::thread::{self, ThreadId};
+
+use actors::ActorSystem;
+
+/// Trait to implement for the services shared by the actors of an actor system, such as metrics,
+/// cluster membership or persistence.
+///
+/// An actor system has at most one instance of each extension, created the first time it is
+/// registered with `ActorSystem::register_extension`, and then given by `ActorSystem::extension`.
+pub trait Extension: Send + Sync + 'static {
+    /// Creates the extension of `system`.
+    ///
+    /// It can spawn its own actors under `/system` with `system_actor_of`, and register the other
+    /// extensions it depends on. They must not depend on it in turn, such a cycle panics.
+    fn create(system: &ActorSystem) -> Self where Self: Sized;
+
+    /// Method called when the actor system is shut down, the extensions are shut down in reverse
+    /// registration order.
+    fn shutdown(&self) {}
+}
+
+/// Extensions being created by the thread owning the creation of a registry.
+struct Creation {
+    owner: Option<ThreadId>,
+    // The extensions being created, each one by the `create` of the previous one.
+    stack: Vec<(TypeId, &'static str)>,
+}
+
+/// Ownership of the creation of a registry by the current thread for the extension `E`, it is
+/// released when this is dropped.
+struct Creating<'a> {
+    extensions: &'a Extensions,
+}
+
+impl<'a> Creating<'a> {
+    /// Waits for the creations made by other threads, an extension created from the `create` of
+    /// another one is created by the same thread without waiting.
+    ///
+    /// This panics if `E` is already being created by this thread, as the extensions depend on
+    /// each other.
+    fn enter<E: Extension>(extensions: &'a Extensions) -> Creating<'a> {
+        let me = thread::current().id();
+        let mut creation = extensions.creation.lock().unwrap();
+        while creation.owner.is_some() && creation.owner != Some(me) {
+            creation = extensions.creation_done.wait(creation).unwrap();
+        }
+        if creation.stack.iter().any(|&(id, _)| id == TypeId::of::<E>()) {
+            let mut cycle: Vec<&str> = creation.stack.iter().map(|&(_, name)| name).collect();
+            cycle.push(type_name::<E>());
+            // The lock is released before panicking, the creations of the extensions of the cycle
+            // are then released as the panic unwinds them.
+            drop(creation);
+            panic!("The extensions depend on each other: {}", cycle.join(" -> "));
+        }
+        creation.owner = Some(me);
+        creation.stack.push((TypeId::of::<E>(), type_name::<E>()));
+        Creating { extensions: extensions }
+    }
+}
+
+impl<'a> Drop for Creating<'a> {
+    fn drop(&mut self) {
+        // The lock is never held while an extension is created, so it is not poisoned by a panic.
+        let mut creation = self.extensions.creation.lock().unwrap();
+        creation.stack.pop();
+        if creation.stack.is_empty() {
+            creation.owner = None;
+            self.extensions.creation_done.notify_all();
+        }
+    }
+}
+
+struct Registered {
+    by_type: HashMap<TypeId, Arc<Any + Send + Sync>>,
+    // The extensions in registration order, to shut them down in reverse.
+    in_order: Vec<Arc<Extension>>,
+}
+
+/// Registry of the extensions of an actor system, one instance per type.
+///
+/// The actors get the extensions with `ActorSystem::extension`, or have them injected with
+/// `Props::with_extension`.
+///
+/// Each registry has its own creation lock, owned by one thread at a time: the extensions
+/// registered from the `create` of another one are created by the same thread.
+pub struct Extensions {
+    registered: RwLock<Registered>,
+    // Serializes the creations, so that each extension is created once.
+    creation: Mutex<Creation>,
+    creation_done: Condvar,
+}
+
+impl Extensions {
+    /// Creates an empty registry.
+    pub fn new() -> Extensions {
+        Extensions {
+            registered: RwLock::new(Registered {
+                by_type: HashMap::new(),
+                in_order: Vec::new(),
+            }),
+            creation: Mutex::new(Creation {
+                owner: None,
+                stack: Vec::new(),
+            }),
+            creation_done: Condvar::new(),
+        }
+    }
+
+    /// Gives the extension of type `E`, creating it for `system` if it is not registered yet.
+    pub fn get_or_create<E: Extension>(&self, system: &ActorSystem) -> Arc<E> {
+        if let Some(extension) = self.get::<E>() {
+            return extension;
+        }
+        let _creating = Creating::enter::<E>(self);
+        // It may have been created while we were waiting for the lock.
+        if let Some(extension) = self.get::<E>() {
+            return extension;
+        }
+        let extension = Arc::new(E::create(system));
+        let mut registered = self.registered.write().unwrap();
+        registered.by_type.insert(TypeId::of::<E>(), extension.clone());
+        registered.in_order.push(extension.clone());
+        extension
+    }
+
+    /// Gives the extension of type `E`, if it is registered.
+    pub fn get<E: Extension>(&self) -> Option<Arc<E>> {
+        let registered = self.registered.read().unwrap();
+        registered.by_type.get(&TypeId::of::<E>()).cloned().map(|extension| {
+            match extension.downcast::<E>() {
+                Ok(extension) => extension,
+                Err(_) => panic!("An extension is registered under the type id of another type"),
+            }
+        })
+    }
+
+    /// Shuts the extensions down in reverse registration order, and drops them.
+    pub fn shutdown(&self) {
+        let in_order = {
+            let mut registered = self.registered.write().unwrap();
+            registered.by_type.clear();
+            ::std::mem::replace(&mut registered.in_order, Vec::new())
+        };
+        for extension in in_order.iter().rev() {
+            extension.shutdown();
+        }
+    }
+}
+
+impl Default for Extensions {
+    fn default() -> Extensions {
+        Extensions::new()
+    }
+}
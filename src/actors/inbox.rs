@@ -0,0 +1,129 @@
+use std::any::Any;
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use actors::{ActorRef, ActorSystem, InnerMessage, Message, MessageReceiver, SystemMessage};
+
+/// A received message and its sender.
+struct Received {
+    message: Box<Any + Send>,
+    sender: ActorRef,
+}
+
+struct InboxQueue {
+    messages: Mutex<VecDeque<Received>>,
+    condvar: Condvar,
+}
+
+impl MessageReceiver for InboxQueue {
+    fn receive(&self, message: InnerMessage, sender: ActorRef, _receiver: &ActorRef) {
+        let message: Box<Any + Send> = match message {
+            InnerMessage::Message(message) => message,
+            InnerMessage::Control(message) => Box::new(message),
+        };
+        self.messages.lock().unwrap().push_back(Received {
+            message: message,
+            sender: sender,
+        });
+        self.condvar.notify_all();
+    }
+}
+
+/// An `ActorRef` with its own queue, used to talk to actors from outside of the actor system
+/// (from `main` or any other thread).
+///
+/// The messages sent with an inbox have it as sender, so the replies are queued in it and can be
+/// taken with `receive` or `select`. Inboxes are created with `ActorSystem::inbox`, each one is
+/// registered under `/system` with a generated name until it is dropped.
+///
+/// Termination notices of actors watched with `watch` are received as `ControlMessage`s.
+pub struct Inbox {
+    system: ActorSystem,
+    actor_ref: ActorRef,
+    queue: Arc<InboxQueue>,
+    last_sender: Mutex<Option<ActorRef>>,
+}
+
+impl Inbox {
+    /// Creates a new inbox, this is what `ActorSystem::inbox` does.
+    pub fn new(system: &ActorSystem) -> Inbox {
+        let queue = Arc::new(InboxQueue {
+            messages: Mutex::new(VecDeque::new()),
+            condvar: Condvar::new(),
+        });
+        Inbox {
+            system: system.clone(),
+            actor_ref: system.system_receiver_of_anonymous(queue.clone()),
+            queue: queue,
+            last_sender: Mutex::new(None),
+        }
+    }
+
+    /// Gives the ActorRef of the inbox.
+    pub fn actor_ref(&self) -> ActorRef {
+        self.actor_ref.clone()
+    }
+
+    /// Sends a message to `to`, with the inbox as sender.
+    pub fn send<MessageTo: Message>(&self, to: ActorRef, message: MessageTo) {
+        self.actor_ref.tell_to(to, message);
+    }
+
+    /// Sender of the last message taken from the inbox.
+    pub fn last_sender(&self) -> Option<ActorRef> {
+        self.last_sender.lock().unwrap().clone()
+    }
+
+    /// Takes the next message received by the inbox, waiting at most `timeout`.
+    ///
+    /// Gives None if no message was received in time.
+    pub fn receive(&self, timeout: Duration) -> Option<Box<Any + Send>> {
+        self.select(timeout, |_| true)
+    }
+
+    /// Takes the first message for which `predicate` returns true, waiting at most `timeout` for
+    /// it.
+    ///
+    /// The other messages are left in the inbox, in order. Gives None if no such message was
+    /// received in time.
+    pub fn select<F>(&self, timeout: Duration, mut predicate: F) -> Option<Box<Any + Send>>
+        where F: FnMut(&Any) -> bool
+    {
+        let deadline = Instant::now() + timeout;
+        let mut messages = self.queue.messages.lock().unwrap();
+        // Messages before this index were already rejected by the predicate.
+        let mut checked = 0;
+        loop {
+            while checked < messages.len() {
+                if predicate(&*messages[checked].message) {
+                    let received = messages.remove(checked).unwrap();
+                    *self.last_sender.lock().unwrap() = Some(received.sender);
+                    return Some(received.message);
+                }
+                checked += 1;
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return None;
+            }
+            messages = self.queue.condvar.wait_timeout(messages, deadline - now).unwrap().0;
+        }
+    }
+
+    /// Monitors `actor_ref`, a termination notice will be received by the inbox when it stops.
+    pub fn watch(&self, actor_ref: &ActorRef) {
+        actor_ref.receive_system_message(SystemMessage::Watch(self.actor_ref.clone()));
+    }
+
+    /// Stops monitoring `actor_ref`.
+    pub fn unwatch(&self, actor_ref: &ActorRef) {
+        actor_ref.receive_system_message(SystemMessage::Unwatch(self.actor_ref.clone()));
+    }
+}
+
+impl Drop for Inbox {
+    fn drop(&mut self) {
+        self.system.stop_system_receiver(&self.actor_ref);
+    }
+}
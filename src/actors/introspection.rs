@@ -0,0 +1,127 @@
+use std::fmt::Write;
+use std::sync::Arc;
+
+use actors::{ActorPath, ActorState};
+
+/// State of an actor at the time a snapshot was taken, with the snapshots of its children.
+#[derive(Clone, Debug)]
+pub struct ActorSnapshot {
+    /// Path of the actor.
+    pub path: Arc<ActorPath>,
+    /// Uid of the incarnation of the actor.
+    pub uid: u64,
+    /// Whether the actor is started, running or failed.
+    pub state: ActorState,
+    /// Number of messages waiting in the mailbox.
+    pub mailbox_len: usize,
+    /// Number of system messages waiting in the system mailbox.
+    pub system_mailbox_len: usize,
+    /// Number of times the actor was restarted after a failure.
+    pub restart_count: usize,
+    /// Snapshots of the children of the actor.
+    pub children: Vec<ActorSnapshot>,
+}
+
+impl ActorSnapshot {
+    /// Number of children of the actor.
+    pub fn child_count(&self) -> usize {
+        self.children.len()
+    }
+
+    /// Finds the snapshot of the actor at `path` among this actor and its descendants.
+    pub fn find(&self, path: &str) -> Option<&ActorSnapshot> {
+        if *self.path.logical_path() == path {
+            return Some(self);
+        }
+        self.children.iter().filter_map(|child| child.find(path)).next()
+    }
+
+    fn name(&self) -> &str {
+        let path = self.path.logical_path();
+        match path.rfind('/') {
+            Some(i) if i + 1 < path.len() => &path[i + 1..],
+            _ => path,
+        }
+    }
+
+    fn write_text(&self, out: &mut String, depth: usize) {
+        let _ = writeln!(out,
+                         "{}{} [{:?}] mailbox: {}, system mailbox: {}, restarts: {}, children: {}",
+                         "  ".repeat(depth),
+                         self.name(),
+                         self.state,
+                         self.mailbox_len,
+                         self.system_mailbox_len,
+                         self.restart_count,
+                         self.child_count());
+        for child in &self.children {
+            child.write_text(out, depth + 1);
+        }
+    }
+
+    fn write_dot(&self, out: &mut String) {
+        let path = self.path.logical_path();
+        let _ = writeln!(out,
+                         "    \"{}\" [label=\"{}\\n{:?}\\nmailbox: {}, system mailbox: {}\\nrestarts: {}\"];",
+                         path,
+                         self.name(),
+                         self.state,
+                         self.mailbox_len,
+                         self.system_mailbox_len,
+                         self.restart_count);
+        for child in &self.children {
+            let _ = writeln!(out, "    \"{}\" -> \"{}\";", path, child.path.logical_path());
+            child.write_dot(out);
+        }
+    }
+}
+
+/// Snapshot of the hierarchies of an actor system, given by `ActorSystem::snapshot`.
+///
+/// The actors are not stopped while the snapshot is taken, so it is only a consistent view for
+/// each actor, not for the whole system.
+#[derive(Clone, Debug)]
+pub struct SystemSnapshot {
+    /// Name of the actor system.
+    pub name: String,
+    /// Snapshot of the `/user` actor and of the actors created with `actor_of`.
+    pub user: Option<ActorSnapshot>,
+    /// Snapshot of the `/system` actor and of the system actors.
+    pub system: Option<ActorSnapshot>,
+    /// Snapshot of the `/temp` actor and of the temporary actors.
+    pub temp: Option<ActorSnapshot>,
+}
+
+impl SystemSnapshot {
+    /// Finds the snapshot of the actor at `path`, such as `/user/foo`.
+    pub fn find(&self, path: &str) -> Option<&ActorSnapshot> {
+        self.roots().filter_map(|root| root.find(path)).next()
+    }
+
+    /// Dumps the hierarchies as an indented text tree, one actor per line.
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "{}", self.name);
+        for root in self.roots() {
+            root.write_text(&mut out, 1);
+        }
+        out
+    }
+
+    /// Dumps the hierarchies as a Graphviz DOT digraph.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "digraph \"{}\" {{", self.name);
+        let _ = writeln!(out, "    \"/\" [label=\"{}\"];", self.name);
+        for root in self.roots() {
+            let _ = writeln!(out, "    \"/\" -> \"{}\";", root.path.logical_path());
+            root.write_dot(&mut out);
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    fn roots<'a>(&'a self) -> Box<Iterator<Item = &'a ActorSnapshot> + 'a> {
+        Box::new(self.user.iter().chain(self.system.iter()).chain(self.temp.iter()))
+    }
+}
@@ -0,0 +1,313 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use actors::ActorPath;
+
+/// Upper bounds, in seconds, of the buckets of the histograms.
+const BUCKETS: [f64; 12] = [0.00001, 0.00005, 0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5,
+                            1.0, 5.0];
+
+fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1_000_000_000.0
+}
+
+/// Histogram of durations, with fixed buckets going from 10µs to 5s.
+#[derive(Clone, Copy, Debug)]
+pub struct Histogram {
+    // Number of observations in each bucket, the last one is for the values above all the bounds.
+    counts: [u64; 13],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new() -> Histogram {
+        Histogram {
+            counts: [0; 13],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, duration: Duration) {
+        let value = seconds(duration);
+        let bucket = BUCKETS.iter().position(|bound| value <= *bound).unwrap_or(BUCKETS.len());
+        self.counts[bucket] += 1;
+        self.sum += value;
+        self.count += 1;
+    }
+
+    /// Number of observations.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Sum of the observations, in seconds.
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+
+    /// Mean of the observations, in seconds.
+    pub fn mean(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.sum / self.count as f64
+        }
+    }
+
+    /// Cumulative counts of the buckets, as pairs of upper bound in seconds and number of
+    /// observations lower or equal to it, the last bound is infinity.
+    pub fn buckets(&self) -> Vec<(f64, u64)> {
+        let mut total = 0;
+        let mut res = Vec::new();
+        for (i, count) in self.counts.iter().enumerate() {
+            total += *count;
+            res.push((BUCKETS.get(i).cloned().unwrap_or(::std::f64::INFINITY), total));
+        }
+        res
+    }
+}
+
+/// Metrics of an actor path, they are kept when the actor is stopped and created again.
+#[derive(Clone, Copy, Debug)]
+pub struct ActorMetrics {
+    /// Number of messages handled.
+    pub messages: u64,
+    /// Time spent by the actor handling its messages.
+    pub processing_time: Histogram,
+    /// Time the messages waited in the mailbox.
+    pub queue_time: Histogram,
+    /// Number of restarts after a failure.
+    pub restarts: u64,
+    /// Number of messages sent to the actor which ended in the dead letters.
+    pub dead_letters: u64,
+}
+
+impl ActorMetrics {
+    fn new() -> ActorMetrics {
+        ActorMetrics {
+            messages: 0,
+            processing_time: Histogram::new(),
+            queue_time: Histogram::new(),
+            restarts: 0,
+            dead_letters: 0,
+        }
+    }
+}
+
+/// Metrics of a consumer thread.
+#[derive(Clone, Copy, Debug)]
+pub struct ThreadMetrics {
+    /// Identifier of the thread, in order of creation.
+    pub id: usize,
+    /// Number of messages handled by the thread.
+    pub messages: u64,
+    /// Time spent handling messages.
+    pub busy: Duration,
+    started: Instant,
+    stopped: Option<Instant>,
+}
+
+impl ThreadMetrics {
+    /// Whether the thread is still running.
+    pub fn is_running(&self) -> bool {
+        self.stopped.is_none()
+    }
+
+    /// Fraction of its life the thread spent handling messages, between 0 and 1.
+    pub fn utilization(&self) -> f64 {
+        let end = self.stopped.unwrap_or_else(Instant::now);
+        let lifetime = seconds(end.duration_since(self.started));
+        if lifetime == 0.0 {
+            0.0
+        } else {
+            (seconds(self.busy) / lifetime).min(1.0)
+        }
+    }
+}
+
+struct MetricsState {
+    actors: BTreeMap<String, ActorMetrics>,
+    threads: Vec<ThreadMetrics>,
+}
+
+impl MetricsState {
+    fn actor(&mut self, path: &ActorPath) -> &mut ActorMetrics {
+        self.actors.entry(path.logical_path().clone()).or_insert_with(ActorMetrics::new)
+    }
+}
+
+/// Registry of the metrics of an actor system, given by `ActorSystem::metrics`.
+///
+/// The metrics are collected by the actor cells and the consumer threads, they can be pulled with
+/// `actor` / `actors` / `threads` or exposed with `to_prometheus`.
+#[derive(Clone)]
+pub struct Metrics {
+    state: Arc<Mutex<MetricsState>>,
+}
+
+impl Metrics {
+    /// Creates an empty registry.
+    pub fn new() -> Metrics {
+        Metrics {
+            state: Arc::new(Mutex::new(MetricsState {
+                actors: BTreeMap::new(),
+                threads: Vec::new(),
+            })),
+        }
+    }
+
+    /// Records a message handled by the actor at `path`.
+    pub fn record_message(&self, path: &ActorPath, queue_time: Duration, processing_time: Duration) {
+        let mut state = self.state.lock().unwrap();
+        let actor = state.actor(path);
+        actor.messages += 1;
+        actor.queue_time.observe(queue_time);
+        actor.processing_time.observe(processing_time);
+    }
+
+    /// Records a restart of the actor at `path`.
+    pub fn record_restart(&self, path: &ActorPath) {
+        self.state.lock().unwrap().actor(path).restarts += 1;
+    }
+
+    /// Records a message sent to the actor at `path` which ended in the dead letters.
+    pub fn record_dead_letter(&self, path: &ActorPath) {
+        self.state.lock().unwrap().actor(path).dead_letters += 1;
+    }
+
+    /// Registers a new consumer thread, returns its id.
+    pub fn register_thread(&self) -> usize {
+        let mut state = self.state.lock().unwrap();
+        let id = state.threads.len();
+        state.threads.push(ThreadMetrics {
+            id: id,
+            messages: 0,
+            busy: Duration::from_millis(0),
+            started: Instant::now(),
+            stopped: None,
+        });
+        id
+    }
+
+    /// Records a message handled by the consumer thread `id` in `busy` time.
+    pub fn record_thread_busy(&self, id: usize, busy: Duration) {
+        if let Some(thread) = self.state.lock().unwrap().threads.get_mut(id) {
+            thread.messages += 1;
+            thread.busy += busy;
+        }
+    }
+
+    /// Records the termination of the consumer thread `id`.
+    pub fn record_thread_stopped(&self, id: usize) {
+        if let Some(thread) = self.state.lock().unwrap().threads.get_mut(id) {
+            thread.stopped = Some(Instant::now());
+        }
+    }
+
+    /// Metrics of the actor at `path`, such as `/user/foo`.
+    pub fn actor(&self, path: &str) -> Option<ActorMetrics> {
+        self.state.lock().unwrap().actors.get(path).cloned()
+    }
+
+    /// Metrics of all the actor paths, sorted by path.
+    pub fn actors(&self) -> Vec<(String, ActorMetrics)> {
+        self.state
+            .lock()
+            .unwrap()
+            .actors
+            .iter()
+            .map(|(path, metrics)| (path.clone(), *metrics))
+            .collect()
+    }
+
+    /// Metrics of all the consumer threads ever started, including the stopped ones.
+    pub fn threads(&self) -> Vec<ThreadMetrics> {
+        self.state.lock().unwrap().threads.clone()
+    }
+
+    /// Exposes the metrics in the Prometheus text format.
+    pub fn to_prometheus(&self) -> String {
+        let actors = self.actors();
+        let threads = self.threads();
+        let mut out = String::new();
+
+        write_header(&mut out, "robots_actor_messages_total", "counter", "Messages handled by the actor.");
+        for &(ref path, ref metrics) in &actors {
+            let _ = writeln!(out, "robots_actor_messages_total{{path=\"{}\"}} {}", path, metrics.messages);
+        }
+        write_header(&mut out,
+                     "robots_actor_processing_seconds",
+                     "histogram",
+                     "Time spent by the actor handling a message.");
+        for &(ref path, ref metrics) in &actors {
+            write_histogram(&mut out, "robots_actor_processing_seconds", path, &metrics.processing_time);
+        }
+        write_header(&mut out,
+                     "robots_actor_queue_seconds",
+                     "histogram",
+                     "Time a message waited in the mailbox of the actor.");
+        for &(ref path, ref metrics) in &actors {
+            write_histogram(&mut out, "robots_actor_queue_seconds", path, &metrics.queue_time);
+        }
+        write_header(&mut out, "robots_actor_restarts_total", "counter", "Restarts of the actor.");
+        for &(ref path, ref metrics) in &actors {
+            let _ = writeln!(out, "robots_actor_restarts_total{{path=\"{}\"}} {}", path, metrics.restarts);
+        }
+        write_header(&mut out,
+                     "robots_actor_dead_letters_total",
+                     "counter",
+                     "Messages sent to the actor which ended in the dead letters.");
+        for &(ref path, ref metrics) in &actors {
+            let _ = writeln!(out,
+                             "robots_actor_dead_letters_total{{path=\"{}\"}} {}",
+                             path,
+                             metrics.dead_letters);
+        }
+        write_header(&mut out,
+                     "robots_dispatcher_messages_total",
+                     "counter",
+                     "Messages handled by the consumer thread.");
+        for thread in &threads {
+            let _ = writeln!(out, "robots_dispatcher_messages_total{{thread=\"{}\"}} {}", thread.id, thread.messages);
+        }
+        write_header(&mut out,
+                     "robots_dispatcher_utilization",
+                     "gauge",
+                     "Fraction of its life the consumer thread spent handling messages.");
+        for thread in &threads {
+            let _ = writeln!(out,
+                             "robots_dispatcher_utilization{{thread=\"{}\"}} {}",
+                             thread.id,
+                             thread.utilization());
+        }
+        out
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Metrics {
+        Metrics::new()
+    }
+}
+
+fn write_header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn write_histogram(out: &mut String, name: &str, path: &str, histogram: &Histogram) {
+    for (bound, count) in histogram.buckets() {
+        let bound = if bound.is_infinite() {
+            "+Inf".to_owned()
+        } else {
+            format!("{}", bound)
+        };
+        let _ = writeln!(out, "{}_bucket{{path=\"{}\",le=\"{}\"}} {}", name, path, bound, count);
+    }
+    let _ = writeln!(out, "{}_sum{{path=\"{}\"}} {}", name, path, histogram.sum());
+    let _ = writeln!(out, "{}_count{{path=\"{}\"}} {}", name, path, histogram.count());
+}
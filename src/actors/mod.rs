@@ -1,9 +1,27 @@
 pub use std::any::Any;
 
-pub use self::actor_cell::{ActorCell, ActorContext, ControlMessage, InnerMessage, SystemMessage};
-pub use self::actor_ref::{ActorPath, ActorRef};
+pub use self::actor_cell::{ActorCell, ActorContext, ActorCreationError, ActorState, ControlMessage,
+                           InnerMessage, SystemMessage};
+pub use self::actor_mut::{ActorMut, MutProps};
+pub use self::actor_ref::{ActorPath, ActorRef, MessageReceiver};
 pub use self::actor_system::ActorSystem;
+pub use self::ask::AskTimeout;
+pub use self::async_actor::{AsyncActor, AsyncProps, BoxFuture, EventualFuture};
+pub use self::blocking::{AskFuture, BlockingAskPolicy};
+pub use self::dead_letters::DeadLetter;
+pub use self::dispatch::Dispatch;
+pub use self::event_stream::{EventStream, UnhandledMessage};
+pub use self::extension::{Extension, Extensions};
+pub use self::inbox::Inbox;
+pub use self::introspection::{ActorSnapshot, SystemSnapshot};
+pub use self::pipe::{pipe_to, Status};
+pub use self::metrics::{ActorMetrics, Histogram, Metrics, ThreadMetrics};
 pub use self::props::Props;
+pub use self::reply_to::ReplyTo;
+pub use self::scheduler::{Cancellable, Scheduler};
+pub use self::watchdog::{SlowReceive, Watchdog};
+
+use self::event_stream::publish_unhandled;
 
 /// Module for ActorRef and CanReceive, the interface given to the user to interract with  actors.
 pub mod actor_ref;
@@ -17,6 +35,35 @@ pub mod props;
 /// Module with the internals of Actors.
 pub mod actor_cell;
 
+/// Module with the Scheduler, used to send messages in the future.
+pub mod scheduler;
+
+/// Module with the `std::future` integration: awaitable asks and asynchronous actors.
+pub mod async_actor;
+
+/// Module running the actors on a tokio runtime, and sending messages between tokio tasks and
+/// actors.
+#[cfg(feature = "tokio")]
+pub mod tokio_bridge;
+
+/// Module with the snapshots of the actor hierarchies, used for introspection.
+pub mod introspection;
+
+/// Module with the metrics registry of the actor systems.
+pub mod metrics;
+
+/// Module with the event stream of the actor systems, and the unhandled messages events.
+pub mod event_stream;
+
+/// Module with the extensions of the actor systems and their registry.
+pub mod extension;
+
+/// Module with the watchdog reporting the actors blocking their consumer thread.
+pub mod watchdog;
+
+/// Module detecting the actors blocking a consumer thread on an ask.
+pub mod blocking;
+
 /// Module containing the original actor.
 mod cthulhu;
 
@@ -26,6 +73,28 @@ mod root_actor;
 /// Module with the name resolver actor.
 mod name_resolver;
 
+/// Module with the temporary actors used by `ActorContext::ask_with`.
+mod ask;
+
+/// Module with the `Inbox`, used to send messages and receive replies from outside of the actors.
+mod inbox;
+
+/// Module with the `receive!` macro and the `Dispatch` trait, converting the `Box<Any>` messages
+/// to typed ones.
+mod dispatch;
+
+/// Module with `ActorMut`, the actors mutating their state with `&mut self`.
+mod actor_mut;
+
+/// Module with `ReplyTo`, the typed handles to reply to a request, and the message adapters.
+mod reply_to;
+
+/// Module with `pipe_to`, sending the outcome of a future to an actor.
+mod pipe;
+
+/// Module with the dead letters actor, receiving the messages that could not be delivered.
+mod dead_letters;
+
 /// Trait to be implemented by messages, this is automatically given if a struct is
 /// already `Clone + Send + Sync + 'static + Any`.
 pub trait Message: Clone + Send + Sync + 'static + Any {}
@@ -51,19 +120,42 @@ pub trait Actor: Send + Sync + 'static {
     // done in order to have nicer code for the downcasts (indeed, I can't implement downcast
     // methods for Box<Message>).
     // Checks for sending data with the Message trait is done in the sending phase.
+    //
+    // The messages it does not handle should be given to `unhandled`.
     fn receive(&self, message: Box<Any>, context: ActorCell);
 
-    /// Method called when a monitored actor is terminated.
+    /// Method called by `receive` with the messages it does not handle.
+    ///
+    /// By default it publishes an `UnhandledMessage` event to the event stream of the actor
+    /// system, and logs it at debug level if `ActorSystem::set_log_unhandled` was set.
+    ///
+    /// The built-in actors call it too, such as the `FsmActor`s with the messages received in a
+    /// state without handler.
+    fn unhandled(&self, message: Box<Any>, context: ActorCell) {
+        publish_unhandled(&*message, &context);
+    }
+
+    /// Method called when a monitored actor is terminated, actors are monitored with
+    /// `ActorContext::watch`.
     ///
     /// This is put in a separated method because match in rust must check all variations and we
     /// chose not to force the user to make a case for terminations if it doesn not monitor any
     /// actor.
-    // NOTE: this currently panic! because termination notices are not sent in the current
-    // implementation.
-    fn receive_termination(&self, _context: ActorCell) {
+    // NOTE: this panic! by default because only actors that watch other actors receive
+    // termination notices.
+    fn receive_termination(&self, _terminated: ActorRef, _context: ActorCell) {
         panic!("Not implemented");
     }
 
+    /// Method called before a regular message is received, if it returns true the message is
+    /// stashed instead.
+    ///
+    /// Stashed messages are kept in the order they arrived until `ActorContext::unstash_all` is
+    /// called (or the actor is restarted), they are then handled before the rest of the mailbox.
+    fn stashes(&self, _message: &Any) -> bool {
+        false
+    }
+
     /// Method called before the Actor is started.
     fn pre_start(&self, _context: ActorCell) {}
 
@@ -79,4 +171,13 @@ pub trait Actor: Send + Sync + 'static {
     fn post_restart(&self, context: ActorCell) {
         self.pre_start(context);
     }
+
+    /// Method called by the actor cell to handle a message, it calls `receive` by default.
+    ///
+    /// When it returns a future the actor handles no other message until the future is completed,
+    /// this is how `AsyncActor`s are run.
+    fn receive_async(&self, message: Box<Any + Send>, context: ActorCell) -> Option<BoxFuture> {
+        self.receive(message, context);
+        None
+    }
 }
@@ -4,7 +4,7 @@ use std::sync::Mutex;
 use std::any::Any;
 use std::sync::Arc;
 
-use actors::{Actor, ActorCell, ActorContext, ActorPath, ActorRef};
+use actors::{Actor, ActorCell, ActorContext, ActorPath, ActorRef, ReplyTo};
 
 /// Messages handled by the NameResolver.
 #[derive(Clone)]
@@ -16,7 +16,7 @@ pub enum ResolveRequest {
     Remove(Arc<ActorPath>),
 
     /// Used when we want to find the actor associated to a path.
-    Get(String),
+    Get(String, ReplyTo<Option<ActorRef>>),
 }
 
 /// Name resolving actor.
@@ -34,8 +34,8 @@ pub struct NameResolver {
 
 impl Actor for NameResolver {
     fn receive(&self, message: Box<Any>, context: ActorCell) {
-        if let Ok(message) = Box::<Any>::downcast::<ResolveRequest>(message) {
-            match *message {
+        match Box::<Any>::downcast::<ResolveRequest>(message) {
+            Ok(message) => match *message {
                 ResolveRequest::Add(address) => {
                     let mut index = self.index.lock().unwrap();
                     index.insert(address.path(), address);
@@ -44,11 +44,13 @@ impl Actor for NameResolver {
                     let mut index = self.index.lock().unwrap();
                     index.remove(&address);
                 }
-                ResolveRequest::Get(address) => {
+                ResolveRequest::Get(address, reply_to) => {
                     let index = self.index.lock().unwrap();
-                    context.tell(context.sender(), index.get(&ActorPath::new_local(address)).cloned());
+                    reply_to.tell(index.get(&ActorPath::new_local(address)).cloned(),
+                                  context.actor_ref());
                 }
-            }
+            },
+            Err(message) => self.unhandled(message, context),
         }
     }
 }
@@ -0,0 +1,52 @@
+extern crate eventual;
+
+use std::any::Any;
+
+use self::eventual::{Async, AsyncError};
+
+use actors::{ActorRef, InnerMessage, Message};
+use actors::watchdog::register_message_type;
+
+/// Message sent by `pipe_to` when the future does not give a value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Status<E> {
+    /// The future failed with this error.
+    Failure(E),
+    /// The future was dropped without being completed, for example because an asked actor was
+    /// stopped.
+    Aborted,
+}
+
+/// Sends the value of `future` to `to` as a message from `sender` once it is completed, or a
+/// `Status::Failure` if it fails.
+///
+/// Nothing blocks while waiting for the future, the message is sent by the thread completing it,
+/// so this can be used from inside an actor whatever the `BlockingAskPolicy` is. The values of the
+/// `AskFuture`s given by `ActorRef::ask` are already boxed messages, they are sent as is so that
+/// the target receives the reply as if it had been sent to it.
+pub fn pipe_to<A>(future: A, to: ActorRef, sender: ActorRef)
+    where A: Async,
+          A::Error: Message
+{
+    future.receive(move |result| {
+        let message: Box<Any + Send> = match result {
+            Ok(value) => {
+                register_message_type::<A::Value>();
+                let value: Box<Any + Send> = Box::new(value);
+                match value.downcast::<Box<Any + Send>>() {
+                    Ok(message) => *message,
+                    Err(value) => value,
+                }
+            }
+            Err(AsyncError::Failed(error)) => {
+                register_message_type::<Status<A::Error>>();
+                Box::new(Status::Failure(error))
+            }
+            Err(AsyncError::Aborted) => {
+                register_message_type::<Status<A::Error>>();
+                Box::new(Status::Aborted::<A::Error>)
+            }
+        };
+        to.receive(InnerMessage::Message(message), sender);
+    });
+}
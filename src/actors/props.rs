@@ -1,11 +1,85 @@
+use std::any::Any;
 use std::sync::Arc;
 
-use actors::{Actor, Arguments};
+use actors::{Actor, ActorCell, ActorMut, ActorRef, ActorSystem, Arguments, BoxFuture, Extension};
+
+/// An actor instance, as created by an `ActorFactory` and held by its cell.
+pub enum ActorInstance {
+    /// An `Actor`, its methods take `&self`.
+    Shared(Arc<Actor>),
+    /// An `ActorMut`, owned by the cell which gives it `&mut` access as it never handles two
+    /// messages at the same time.
+    Owned(Box<ActorMut>),
+}
+
+impl ActorInstance {
+    /// Whether the message is to be stashed instead of handled, an `ActorMut` never stashes.
+    pub fn stashes(&self, message: &Any) -> bool {
+        match *self {
+            ActorInstance::Shared(ref actor) => actor.stashes(message),
+            ActorInstance::Owned(_) => false,
+        }
+    }
+
+    /// Handles a message, returns the future to poll if it is handled asynchronously.
+    pub fn receive_async(&mut self,
+                         message: Box<Any + Send>,
+                         context: ActorCell)
+                         -> Option<BoxFuture> {
+        match *self {
+            ActorInstance::Shared(ref actor) => actor.receive_async(message, context),
+            ActorInstance::Owned(ref mut actor) => {
+                actor.receive(message, context);
+                None
+            }
+        }
+    }
+
+    /// Calls `receive_termination` on the actor.
+    pub fn receive_termination(&mut self, terminated: ActorRef, context: ActorCell) {
+        match *self {
+            ActorInstance::Shared(ref actor) => actor.receive_termination(terminated, context),
+            ActorInstance::Owned(ref mut actor) => actor.receive_termination(terminated, context),
+        }
+    }
+
+    /// Calls `pre_start` on the actor.
+    pub fn pre_start(&mut self, context: ActorCell) {
+        match *self {
+            ActorInstance::Shared(ref actor) => actor.pre_start(context),
+            ActorInstance::Owned(ref mut actor) => actor.pre_start(context),
+        }
+    }
+
+    /// Calls `post_stop` on the actor.
+    pub fn post_stop(&mut self) {
+        match *self {
+            ActorInstance::Shared(ref actor) => actor.post_stop(),
+            ActorInstance::Owned(ref mut actor) => actor.post_stop(),
+        }
+    }
+
+    /// Calls `pre_restart` on the actor.
+    pub fn pre_restart(&mut self, context: ActorCell) {
+        match *self {
+            ActorInstance::Shared(ref actor) => actor.pre_restart(context),
+            ActorInstance::Owned(ref mut actor) => actor.pre_restart(context),
+        }
+    }
+
+    /// Calls `post_restart` on the actor.
+    pub fn post_restart(&mut self, context: ActorCell) {
+        match *self {
+            ActorInstance::Shared(ref actor) => actor.post_restart(context),
+            ActorInstance::Owned(ref mut actor) => actor.post_restart(context),
+        }
+    }
+}
 
 /// Public interface of a Props.
 pub trait ActorFactory: Send + Sync {
     /// Creates an Actor instance.
-    fn create(&self) ->  Arc<Actor>;
+    fn create(&self) -> ActorInstance;
 }
 
 /// Props is the current only ActorFactory.
@@ -27,14 +101,57 @@ impl<Args: Arguments, A: Actor> Props<Args, A> {
     }
 }
 
+impl<A: Actor> Props<(), A> {
+    /// Creates a `Props` which is a factory for `A` with the `creator` closure.
+    pub fn from_fn<F>(creator: F) -> Arc<ActorFactory>
+        where F: Fn() -> A + Send + Sync + 'static
+    {
+        Props::new(Arc::new(move |()| creator()), ())
+    }
+}
+
+impl<A: Actor + Default> Props<(), A> {
+    /// Creates a `Props` which is a factory for `A` with `A::default`, it is used as
+    /// `Props::<(), A>::default()`.
+    pub fn default() -> Arc<ActorFactory> {
+        Props::from_fn(A::default)
+    }
+}
+
+impl<S: Send + Sync + 'static, A: Actor> Props<Arc<S>, A> {
+    /// Creates a `Props` which is a factory for `A` with the `creator` closure, which is given the
+    /// shared `service`.
+    ///
+    /// The restarted actors are given the same service, this is how services such as a database
+    /// connection pool are injected in the actors.
+    pub fn with_service<F>(service: Arc<S>, creator: F) -> Arc<ActorFactory>
+        where F: Fn(Arc<S>) -> A + Send + Sync + 'static
+    {
+        Props::new(Arc::new(creator), service)
+    }
+}
+
+impl<E: Extension, A: Actor> Props<Arc<E>, A> {
+    /// Creates a `Props` which is a factory for `A` with the `creator` closure, which is given the
+    /// extension of type `E` of `system`.
+    ///
+    /// The extension is registered, and thus created if needed, once: the restarted actors are
+    /// given the same one.
+    pub fn with_extension<F>(system: &ActorSystem, creator: F) -> Arc<ActorFactory>
+        where F: Fn(Arc<E>) -> A + Send + Sync + 'static
+    {
+        Props::with_service(system.register_extension::<E>(), creator)
+    }
+}
+
 impl<Args: Arguments, A: Actor> ActorFactory for Props<Args, A> {
     /// Creates an Actor instance with the `creator` function and the `args` args.
     ///
     /// This is meant to allow to respawn an Actor when it fails.
-    fn create(&self) -> Arc<Actor> {
+    fn create(&self) -> ActorInstance {
         // FIXME(gamazeps): reopen https://github.com/rust-lang/rust/issues/18343 with an example.
         let args = self.args.clone();
-        Arc::new((self.creator)(args))
+        ActorInstance::Shared(Arc::new((self.creator)(args)))
     }
 }
 
@@ -0,0 +1,139 @@
+use std::any::Any;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use actors::{ActorRef, ActorSystem, InnerMessage, Message, MessageReceiver};
+use actors::watchdog::register_message_type;
+
+// Used to give a unique path to each message adapter.
+static NEXT_ADAPTER_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Typed handle to reply to a request with messages of type `R`.
+///
+/// Requests following this protocol carry the `ReplyTo` of the asker instead of relying on the
+/// sender, so the type of the reply is part of the request:
+///
+/// ```ignore
+/// #[derive(Clone)]
+/// struct GetBalance(ReplyTo<u64>);
+///
+/// let balance: u64 = account.ask_typed(GetBalance).await().unwrap();
+/// ```
+///
+/// An actor asking with its own message type gets a `ReplyTo` from
+/// `ActorContext::message_adapter`.
+pub struct ReplyTo<R: Message> {
+    actor_ref: ActorRef,
+    _reply: PhantomData<R>,
+}
+
+impl<R: Message> ReplyTo<R> {
+    /// Creates a handle replying to `actor_ref`, which must handle messages of type `R`.
+    pub fn new(actor_ref: ActorRef) -> ReplyTo<R> {
+        ReplyTo {
+            actor_ref: actor_ref,
+            _reply: PhantomData,
+        }
+    }
+
+    /// Gives the ActorRef the replies are sent to.
+    pub fn actor_ref(&self) -> ActorRef {
+        self.actor_ref.clone()
+    }
+
+    /// Sends `reply`, as a message sent by `sender` (usually `context.actor_ref()`).
+    pub fn tell(&self, reply: R, sender: ActorRef) {
+        register_message_type::<R>();
+        // This is done in order to avoid a trivial cast warning.
+        let reply: Box<Any + Send> = Box::new(reply);
+        self.actor_ref.receive(InnerMessage::Message(reply), sender);
+    }
+}
+
+impl<R: Message> Clone for ReplyTo<R> {
+    fn clone(&self) -> ReplyTo<R> {
+        ReplyTo::new(self.actor_ref.clone())
+    }
+}
+
+impl<R: Message> PartialEq for ReplyTo<R> {
+    fn eq(&self, other: &ReplyTo<R>) -> bool {
+        self.actor_ref == other.actor_ref
+    }
+}
+
+impl<R: Message> fmt::Debug for ReplyTo<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ReplyTo({})", self.actor_ref)
+    }
+}
+
+/// A reply received by a message adapter, it is sent as is to the actor of the adapter and
+/// converted by its cell just before being handled.
+pub struct AdaptedReply {
+    reply: Box<Any + Send>,
+    adapter: Arc<Fn(Box<Any + Send>) -> Box<Any + Send> + Send + Sync>,
+}
+
+impl AdaptedReply {
+    /// Converts the reply with the adapter.
+    pub fn adapt(self) -> Box<Any + Send> {
+        (self.adapter)(self.reply)
+    }
+}
+
+/// Receiver behind the `ReplyTo` given by `ActorContext::message_adapter`, it sends the replies
+/// to the actor, whose cell converts them before giving them to the actor.
+///
+/// The conversion is thus done by the thread handling the converted message, so an adapter that
+/// panics makes the actor fail and not the one replying. Messages of another type go to the dead
+/// letters.
+pub struct MessageAdapter<R: Message, M: Message> {
+    actor: ActorRef,
+    system: ActorSystem,
+    adapter: Arc<Fn(R) -> M + Send + Sync>,
+}
+
+impl<R: Message, M: Message> MessageAdapter<R, M> {
+    /// Creates the `ReplyTo` converting the replies for `actor` with `adapter`.
+    pub fn reply_to(actor: ActorRef,
+                    system: ActorSystem,
+                    adapter: Arc<Fn(R) -> M + Send + Sync>)
+                    -> ReplyTo<R> {
+        let id = NEXT_ADAPTER_ID.fetch_add(1, Ordering::SeqCst);
+        let path = actor.path().child(format!("$adapter-{}", id));
+        let adapter = MessageAdapter {
+            actor: actor,
+            system: system,
+            adapter: adapter,
+        };
+        register_message_type::<M>();
+        ReplyTo::new(ActorRef::with_receiver(Arc::new(adapter), path))
+    }
+}
+
+impl<R: Message, M: Message> MessageReceiver for MessageAdapter<R, M> {
+    fn receive(&self, message: InnerMessage, sender: ActorRef, _receiver: &ActorRef) {
+        let message = match message {
+            InnerMessage::Message(message) => message,
+            message => return self.system.dead_letter(message, sender, self.actor.clone()),
+        };
+        if !message.is::<R>() {
+            let message = InnerMessage::Message(message);
+            return self.system.dead_letter(message, sender, self.actor.clone());
+        }
+        let adapter = self.adapter.clone();
+        let reply: Box<Any + Send> = Box::new(AdaptedReply {
+            reply: message,
+            adapter: Arc::new(move |reply| {
+                // The type of the reply was checked when it was received.
+                let reply = reply.downcast::<R>().unwrap();
+                let message: Box<Any + Send> = Box::new(adapter(*reply));
+                message
+            }),
+        });
+        self.actor.receive(InnerMessage::Message(reply), sender);
+    }
+}
@@ -8,12 +8,13 @@
 ///   * The `system actor` which is the root for all actors created for the actor system.
 ///
 /// The father of these two actors is Cthulhu.
+///
+/// Their children are created directly in their cell by the actor system, so they do not handle
+/// any message themselves.
 
 use std::any::Any;
-use std::sync::Arc;
 
-use actors::{Actor, ActorCell, ActorContext};
-use actors::props::ActorFactory;
+use actors::{Actor, ActorCell};
 
 pub struct RootActor;
 
@@ -25,11 +26,6 @@ impl RootActor {
 
 impl Actor for RootActor {
     fn receive(&self, message: Box<Any>, context: ActorCell) {
-        if let Ok(message) = Box::<Any>::downcast::<(Arc<ActorFactory>, String)>(message) {
-            let tmp = *message;
-            let (props, name) = tmp;
-            let actor_ref = context.actor_of(props, name);
-            context.tell(context.sender(), actor_ref);
-        }
+        self.unhandled(message, context);
     }
 }
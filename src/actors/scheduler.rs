@@ -0,0 +1,235 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Handle to a scheduled task, used to cancel it.
+#[derive(Clone)]
+pub struct Cancellable {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Cancellable {
+    /// Cancels the task, this does nothing if the task has already been run.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, AtomicOrdering::SeqCst);
+    }
+
+    /// Whether `cancel` has been called.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(AtomicOrdering::SeqCst)
+    }
+}
+
+struct ScheduledTask {
+    // Time since the creation of the scheduler at which the task has to be run.
+    deadline: Duration,
+    // Used to run tasks with the same deadline in the order they were scheduled.
+    id: u64,
+    task: Box<FnOnce() + Send>,
+    cancelled: Arc<AtomicBool>,
+}
+
+// The BinaryHeap is a max heap, so the ordering is reversed to get the earliest task first.
+impl Ord for ScheduledTask {
+    fn cmp(&self, other: &ScheduledTask) -> Ordering {
+        (other.deadline, other.id).cmp(&(self.deadline, self.id))
+    }
+}
+
+impl PartialOrd for ScheduledTask {
+    fn partial_cmp(&self, other: &ScheduledTask) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for ScheduledTask {
+    fn eq(&self, other: &ScheduledTask) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for ScheduledTask {}
+
+struct SchedulerState {
+    tasks: BinaryHeap<ScheduledTask>,
+    start: Instant,
+    // Current time of a virtual clock, None if the scheduler follows the real time.
+    virtual_now: Option<Duration>,
+    next_id: u64,
+    thread_started: bool,
+    shutdown: bool,
+}
+
+/// The scheduler runs tasks after a given delay, it is used to send messages in the future.
+///
+/// Tasks are run on a dedicated thread, which is started when the first task is scheduled, they
+/// should thus be short (typically sending a message).
+///
+/// A scheduler can also follow a virtual clock, which only moves forward when `advance_time` is
+/// called, in that case the tasks are run by the thread calling `advance_time`. This is used to
+/// test time dependent actors without waiting.
+#[derive(Clone)]
+pub struct Scheduler {
+    inner: Arc<(Mutex<SchedulerState>, Condvar)>,
+}
+
+impl Scheduler {
+    /// Creates a new Scheduler following the real time.
+    pub fn new() -> Scheduler {
+        Scheduler::with_clock(None)
+    }
+
+    /// Creates a new Scheduler following a virtual clock, starting at 0.
+    pub fn new_virtual() -> Scheduler {
+        Scheduler::with_clock(Some(Duration::from_millis(0)))
+    }
+
+    fn with_clock(virtual_now: Option<Duration>) -> Scheduler {
+        Scheduler {
+            inner: Arc::new((Mutex::new(SchedulerState {
+                tasks: BinaryHeap::new(),
+                start: Instant::now(),
+                virtual_now: virtual_now,
+                next_id: 0,
+                thread_started: false,
+                shutdown: false,
+            }),
+                             Condvar::new())),
+        }
+    }
+
+    /// Whether the scheduler follows a virtual clock.
+    pub fn is_virtual(&self) -> bool {
+        self.inner.0.lock().unwrap().virtual_now.is_some()
+    }
+
+    /// Time elapsed since the creation of the scheduler, according to its clock.
+    pub fn now(&self) -> Duration {
+        self.inner.0.lock().unwrap().now()
+    }
+
+    /// Time at which the next task is to be run, if any.
+    pub fn next_deadline(&self) -> Option<Duration> {
+        let state = self.inner.0.lock().unwrap();
+        state.tasks
+             .iter()
+             .filter(|task| !task.cancelled.load(AtomicOrdering::SeqCst))
+             .map(|task| task.deadline)
+             .min()
+    }
+
+    /// Moves the virtual clock forward by `duration`, running the tasks that are due, in order.
+    ///
+    /// This panics if the scheduler follows the real time.
+    pub fn advance_time(&self, duration: Duration) {
+        let mut state = self.inner.0.lock().unwrap();
+        let target = match state.virtual_now {
+            Some(now) => now + duration,
+            None => panic!("Tried to advance the time of a scheduler following the real time."),
+        };
+        loop {
+            let due = match state.tasks.peek() {
+                Some(task) => task.deadline <= target,
+                None => false,
+            };
+            if !due {
+                break;
+            }
+            let task = state.tasks.pop().unwrap();
+            // Tasks are run at their deadline, so that the tasks they schedule are relative to it.
+            state.virtual_now = Some(task.deadline);
+            if !task.cancelled.load(AtomicOrdering::SeqCst) {
+                drop(state);
+                (task.task)();
+                state = self.inner.0.lock().unwrap();
+            }
+        }
+        state.virtual_now = Some(target);
+    }
+
+    /// Runs `task` once `delay` has elapsed.
+    pub fn schedule_once<F: FnOnce() + Send + 'static>(&self, delay: Duration, task: F) -> Cancellable {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let &(ref state, ref condvar) = &*self.inner;
+        let mut state = state.lock().unwrap();
+        if state.shutdown {
+            // The system is shut down, the task would never be run anyway.
+            cancelled.store(true, AtomicOrdering::SeqCst);
+            return Cancellable { cancelled: cancelled };
+        }
+        let id = state.next_id;
+        state.next_id += 1;
+        let deadline = state.now() + delay;
+        state.tasks.push(ScheduledTask {
+            deadline: deadline,
+            id: id,
+            task: Box::new(task),
+            cancelled: cancelled.clone(),
+        });
+        if !state.thread_started && state.virtual_now.is_none() {
+            state.thread_started = true;
+            let scheduler = self.clone();
+            thread::spawn(move || scheduler.run());
+        }
+        condvar.notify_one();
+        Cancellable { cancelled: cancelled }
+    }
+
+    /// Stops the scheduler, the pending tasks are dropped without being run.
+    pub fn shutdown(&self) {
+        let &(ref state, ref condvar) = &*self.inner;
+        let mut state = state.lock().unwrap();
+        state.shutdown = true;
+        state.tasks.clear();
+        condvar.notify_one();
+    }
+
+    fn run(&self) {
+        let &(ref state, ref condvar) = &*self.inner;
+        let mut state = state.lock().unwrap();
+        loop {
+            if state.shutdown {
+                return;
+            }
+            let now = state.now();
+            let wait = match state.tasks.peek() {
+                None => None,
+                Some(task) if task.deadline <= now => Some(Duration::from_millis(0)),
+                Some(task) => Some(task.deadline - now),
+            };
+            match wait {
+                None => state = condvar.wait(state).unwrap(),
+                Some(wait) if wait > Duration::from_millis(0) => {
+                    state = condvar.wait_timeout(state, wait).unwrap().0
+                }
+                Some(_) => {
+                    let task = state.tasks.pop().unwrap();
+                    if !task.cancelled.load(AtomicOrdering::SeqCst) {
+                        // The task is run without the lock so that it can schedule other tasks.
+                        drop(state);
+                        (task.task)();
+                        state = self.inner.0.lock().unwrap();
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl SchedulerState {
+    fn now(&self) -> Duration {
+        match self.virtual_now {
+            Some(now) => now,
+            None => self.start.elapsed(),
+        }
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Scheduler {
+        Scheduler::new()
+    }
+}
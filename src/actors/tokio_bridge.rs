@@ -0,0 +1,79 @@
+extern crate tokio;
+
+use std::future::Future as StdFuture;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use self::tokio::runtime::Handle;
+
+use actors::{ActorRef, ActorSystem, Message};
+use actors::blocking::HandlingMarker;
+
+/// Dispatcher of an actor system created with `ActorSystem::with_tokio`, it runs the actor turns
+/// on a tokio runtime instead of the consumer threads.
+///
+/// An actor turn is synchronous code which may block (on an `ask` for example), so the turns are
+/// run with `spawn_blocking`: the worker threads of the runtime are left to the I/O tasks.
+pub struct TokioDispatcher {
+    handle: Handle,
+}
+
+impl TokioDispatcher {
+    /// Creates a dispatcher running the actors on the runtime of `handle`.
+    pub fn new(handle: Handle) -> TokioDispatcher {
+        TokioDispatcher { handle: handle }
+    }
+
+    /// Handle of the runtime the actors are run on.
+    pub fn handle(&self) -> &Handle {
+        &self.handle
+    }
+
+    /// Has the actor of `actor_ref` handle a message on the runtime.
+    pub fn dispatch(&self, actor_system: ActorSystem, actor_ref: ActorRef) {
+        let _ = self.handle.spawn_blocking(move || {
+            let _marker = HandlingMarker::enter(actor_system, actor_ref.path());
+            actor_ref.handle();
+        });
+    }
+}
+
+/// Sends `message` to `to` from outside of the actor system, such as from a tokio task.
+///
+/// There is no actor to reply to, so the replies go to the dead letters (see
+/// `ActorSystem::tell_no_sender`). Use `ActorRef::ask_async` to await a reply instead.
+pub fn tell<MessageTo: Message>(actor_system: &ActorSystem, to: &ActorRef, message: MessageTo) {
+    actor_system.tell_no_sender(to.clone(), message);
+}
+
+/// Task spawned by `ActorContext::spawn_task`, it sends the output of its future to the actor.
+pub struct PipeToActor<T: Message> {
+    future: Pin<Box<StdFuture<Output = T> + Send>>,
+    to: ActorRef,
+}
+
+impl<T: Message> PipeToActor<T> {
+    /// Creates the task sending the output of `future` to `to`, as a message sent by `to` itself.
+    pub fn new<F>(future: F, to: ActorRef) -> PipeToActor<T>
+        where F: StdFuture<Output = T> + Send + 'static
+    {
+        PipeToActor {
+            future: Box::pin(future),
+            to: to,
+        }
+    }
+}
+
+impl<T: Message> StdFuture for PipeToActor<T> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        match self.future.as_mut().poll(cx) {
+            Poll::Ready(output) => {
+                self.to.tell_to(self.to.clone(), output);
+                Poll::Ready(())
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
@@ -0,0 +1,244 @@
+extern crate log;
+
+use std::any::{self, Any, TypeId};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant};
+
+use self::log::warn;
+
+use actors::{ActorPath, ActorRef, ActorSystem, InnerMessage, Message};
+
+// Names of the types of the messages sent so far, a `Box<Any>` only knows its TypeId.
+static MESSAGE_TYPES: RwLock<Option<HashMap<TypeId, &'static str>>> = RwLock::new(None);
+
+/// Records the name of the type `M`, called whenever a message is sent.
+pub fn register_message_type<M: Any>() {
+    let type_id = TypeId::of::<M>();
+    if let Some(ref names) = *MESSAGE_TYPES.read().unwrap() {
+        if names.contains_key(&type_id) {
+            return;
+        }
+    }
+    MESSAGE_TYPES.write()
+                 .unwrap()
+                 .get_or_insert_with(HashMap::new)
+                 .insert(type_id, any::type_name::<M>());
+}
+
+/// Name of the type of `message`, if it was sent as a message.
+pub fn message_type_name(message: &Any) -> &'static str {
+    match *MESSAGE_TYPES.read().unwrap() {
+        Some(ref names) => names.get(&message.type_id()).cloned().unwrap_or("<unknown>"),
+        None => "<unknown>",
+    }
+}
+
+/// Name of the type of an `InnerMessage`.
+pub fn inner_message_type_name(message: &InnerMessage) -> &'static str {
+    match *message {
+        InnerMessage::Message(ref message) => message_type_name(&**message),
+        InnerMessage::Control(_) => "robots::actors::ControlMessage",
+    }
+}
+
+/// Report of an actor taking too long to handle a message, sent to the subscribers of the
+/// watchdog.
+#[derive(Clone, Debug)]
+pub struct SlowReceive {
+    /// Path of the actor.
+    pub path: Arc<ActorPath>,
+    /// Name of the type of the message being handled.
+    pub message_type: &'static str,
+    /// How long the actor had been handling the message when it was reported.
+    pub elapsed: Duration,
+}
+
+/// A message being handled by a consumer thread.
+struct InFlight {
+    path: Arc<ActorPath>,
+    message_type: &'static str,
+    started: Instant,
+    reported: bool,
+    replaced: bool,
+}
+
+struct WatchdogState {
+    in_flight: Mutex<HashMap<ThreadId, InFlight>>,
+    // Threshold and whether stuck consumer threads are replaced, set when the watchdog is started.
+    config: Mutex<Option<(Duration, bool)>>,
+    enabled: AtomicBool,
+    subscribers: Mutex<Vec<ActorRef>>,
+    // Number of consumer threads spawned to replace stuck ones and not yet terminated.
+    replacements: AtomicUsize,
+}
+
+/// Watchdog reporting the actors whose handling of a message exceeds a threshold, as these block
+/// a consumer thread and starve the other actors.
+///
+/// It is started with `ActorSystem::start_watchdog`. Slow handlings are logged and sent as
+/// `SlowReceive` messages to the subscribers. When configured so, a replacement consumer thread
+/// is spawned for each stuck one, and terminated once the stuck handling is over.
+#[derive(Clone)]
+pub struct Watchdog {
+    state: Arc<WatchdogState>,
+}
+
+impl Watchdog {
+    /// Creates a stopped watchdog.
+    pub fn new() -> Watchdog {
+        Watchdog {
+            state: Arc::new(WatchdogState {
+                in_flight: Mutex::new(HashMap::new()),
+                config: Mutex::new(None),
+                enabled: AtomicBool::new(false),
+                subscribers: Mutex::new(Vec::new()),
+                replacements: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    /// Whether the watchdog is running.
+    pub fn is_enabled(&self) -> bool {
+        self.state.enabled.load(Ordering::SeqCst)
+    }
+
+    /// Makes `subscriber` receive the `SlowReceive` reports.
+    pub fn subscribe(&self, subscriber: ActorRef) {
+        self.state.subscribers.lock().unwrap().push(subscriber);
+    }
+
+    /// Stops sending the reports to `subscriber`.
+    pub fn unsubscribe(&self, subscriber: &ActorRef) {
+        self.state.subscribers.lock().unwrap().retain(|s| s != subscriber);
+    }
+
+    /// Starts watching the consumer threads of `actor_system`, this is what
+    /// `ActorSystem::start_watchdog` does.
+    pub fn start(&self, actor_system: ActorSystem, threshold: Duration, replace_stuck_threads: bool) {
+        *self.state.config.lock().unwrap() = Some((threshold, replace_stuck_threads));
+        if self.state.enabled.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let watchdog = self.clone();
+        let _ = thread::spawn(move || {
+            while watchdog.is_enabled() {
+                let threshold = match *watchdog.state.config.lock().unwrap() {
+                    Some((threshold, _)) => threshold,
+                    None => break,
+                };
+                watchdog.check(&actor_system);
+                thread::sleep(::std::cmp::max(threshold / 4, Duration::from_millis(1)));
+            }
+        });
+    }
+
+    /// Stops the watchdog.
+    pub fn stop(&self) {
+        self.state.enabled.store(false, Ordering::SeqCst);
+        *self.state.config.lock().unwrap() = None;
+        self.state.subscribers.lock().unwrap().clear();
+    }
+
+    /// Records that the current thread starts handling a message of type `message_type` for the
+    /// actor at `path`, until the returned guard is dropped (even by a panic).
+    ///
+    /// Returns None when the watchdog is not running.
+    pub fn begin(&self,
+                 actor_system: &ActorSystem,
+                 path: Arc<ActorPath>,
+                 message_type: &'static str)
+                 -> Option<Handling> {
+        if !self.is_enabled() {
+            return None;
+        }
+        self.state.in_flight.lock().unwrap().insert(thread::current().id(),
+                                                    InFlight {
+                                                        path: path,
+                                                        message_type: message_type,
+                                                        started: Instant::now(),
+                                                        reported: false,
+                                                        replaced: false,
+                                                    });
+        Some(Handling {
+            watchdog: self.clone(),
+            actor_system: actor_system.clone(),
+        })
+    }
+
+    fn check(&self, actor_system: &ActorSystem) {
+        let (threshold, replace) = match *self.state.config.lock().unwrap() {
+            Some(config) => config,
+            None => return,
+        };
+        let mut reports = Vec::new();
+        let mut to_spawn = 0;
+        for in_flight in self.state.in_flight.lock().unwrap().values_mut() {
+            let elapsed = in_flight.started.elapsed();
+            if in_flight.reported || elapsed < threshold {
+                continue;
+            }
+            in_flight.reported = true;
+            if replace && !actor_system.is_deterministic() {
+                // Counted here, under the lock, so that the end of the handling sees it.
+                in_flight.replaced = true;
+                self.state.replacements.fetch_add(1, Ordering::SeqCst);
+                to_spawn += 1;
+            }
+            reports.push(SlowReceive {
+                path: in_flight.path.clone(),
+                message_type: in_flight.message_type,
+                elapsed: elapsed,
+            });
+        }
+        for _ in 0..to_spawn {
+            actor_system.spawn_thread();
+        }
+        let subscribers = self.state.subscribers.lock().unwrap().clone();
+        for report in reports {
+            warn!("Actor {} has been handling a {} for {:?}",
+                  report.path.logical_path(),
+                  report.message_type,
+                  report.elapsed);
+            for subscriber in &subscribers {
+                send(subscriber, report.clone(), actor_system.dead_letters());
+            }
+        }
+    }
+
+    /// Number of consumer threads currently running in replacement of stuck ones.
+    pub fn replacements(&self) -> usize {
+        self.state.replacements.load(Ordering::SeqCst)
+    }
+}
+
+impl Default for Watchdog {
+    fn default() -> Watchdog {
+        Watchdog::new()
+    }
+}
+
+/// Guard given by `Watchdog::begin`, the handling of the message is over when it is dropped.
+pub struct Handling {
+    watchdog: Watchdog,
+    actor_system: ActorSystem,
+}
+
+impl Drop for Handling {
+    fn drop(&mut self) {
+        let in_flight = self.watchdog.state.in_flight.lock().unwrap().remove(&thread::current().id());
+        if let Some(in_flight) = in_flight {
+            if in_flight.replaced {
+                self.watchdog.state.replacements.fetch_sub(1, Ordering::SeqCst);
+                self.actor_system.terminate_threads(1);
+            }
+        }
+    }
+}
+
+fn send<M: Message>(to: &ActorRef, message: M, sender: ActorRef) {
+    register_message_type::<M>();
+    to.receive(InnerMessage::Message(Box::new(message)), sender);
+}
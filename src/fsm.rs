@@ -0,0 +1,244 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use actors::{ActorCell, ActorContext, ActorMut, Cancellable};
+
+// Used to give a different id to each state timeout, even across actors and restarts, so that a
+// timeout scheduled for a state we already left is recognized.
+static NEXT_TIMEOUT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// What a state handler receives.
+pub enum FsmEvent {
+    /// A regular message.
+    Message(Box<Any>),
+    /// The timeout of the current state has elapsed without the actor receiving any message.
+    StateTimeout,
+}
+
+/// Message sent to itself by an FsmActor when a state timeout elapses.
+#[derive(Clone)]
+struct StateTimeoutMessage(usize);
+
+/// What a state handler returns: the next state of the actor.
+///
+/// It is built with `goto`, `stay` or `stop`.
+pub struct Transition<S> {
+    next: Next<S>,
+    timeout: Option<Duration>,
+}
+
+enum Next<S> {
+    Goto(S),
+    Stay,
+    Stop,
+}
+
+/// Moves to `state`, the transition callbacks are called even if this is the current state.
+pub fn goto<S>(state: S) -> Transition<S> {
+    Transition {
+        next: Next::Goto(state),
+        timeout: None,
+    }
+}
+
+/// Stays in the current state.
+pub fn stay<S>() -> Transition<S> {
+    Transition {
+        next: Next::Stay,
+        timeout: None,
+    }
+}
+
+/// Stops the actor, `on_termination` will be called.
+pub fn stop<S>() -> Transition<S> {
+    Transition {
+        next: Next::Stop,
+        timeout: None,
+    }
+}
+
+impl<S> Transition<S> {
+    /// Overrides the timeout of the next state for this transition only.
+    pub fn for_max(mut self, timeout: Duration) -> Transition<S> {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+type Handler<S, D> = Box<Fn(FsmEvent, &mut D, ActorCell) -> Transition<S> + Send + Sync>;
+type TransitionCallback<S, D> = Box<Fn(&S, &S, &D, ActorCell) + Send + Sync>;
+type TerminationCallback<S, D> = Box<Fn(&S, &D) + Send + Sync>;
+
+struct StateHandler<S, D> {
+    handler: Handler<S, D>,
+    timeout: Option<Duration>,
+}
+
+/// An ActorMut that is a finite state machine.
+///
+/// The actor is in one of the states `S` and holds some data `D`. For each state a handler is
+/// given with `when`, it is called with each message received in that state and decides of the
+/// next state. A state can have a timeout: if no message is received for that long in the state,
+/// the handler is called with `FsmEvent::StateTimeout`. The messages received in a state without
+/// handler are given to `ActorMut::unhandled`.
+///
+/// As the actor handles one message at a time the handlers get a mutable access to the data.
+///
+/// The state machine is built with `new` followed by calls to `when`, `on_transition`, ... in the
+/// creator function given to `MutProps`, so that a restarted actor starts again in its initial state.
+pub struct FsmActor<S, D> {
+    handlers: HashMap<S, StateHandler<S, D>>,
+    on_transition: Vec<TransitionCallback<S, D>>,
+    on_termination: Option<TerminationCallback<S, D>>,
+    state: S,
+    data: D,
+    // Id and handle of the pending state timeout, if any.
+    timeout: Option<(usize, Cancellable)>,
+}
+
+impl<S, D> FsmActor<S, D>
+    where S: Clone + Eq + Hash + Send + 'static,
+          D: Send + 'static
+{
+    /// Creates a state machine in `state` with `data`.
+    pub fn new(state: S, data: D) -> FsmActor<S, D> {
+        FsmActor {
+            handlers: HashMap::new(),
+            on_transition: Vec::new(),
+            on_termination: None,
+            state: state,
+            data: data,
+            timeout: None,
+        }
+    }
+
+    /// Sets the handler for `state`.
+    pub fn when<F>(self, state: S, handler: F) -> FsmActor<S, D>
+        where F: Fn(FsmEvent, &mut D, ActorCell) -> Transition<S> + Send + Sync + 'static
+    {
+        self.add_handler(state, None, Box::new(handler))
+    }
+
+    /// Sets the handler for `state`, with a state timeout.
+    pub fn when_with_timeout<F>(self, state: S, timeout: Duration, handler: F) -> FsmActor<S, D>
+        where F: Fn(FsmEvent, &mut D, ActorCell) -> Transition<S> + Send + Sync + 'static
+    {
+        self.add_handler(state, Some(timeout), Box::new(handler))
+    }
+
+    /// Adds a callback called on every `goto`, with the previous state, the new state and the
+    /// data.
+    pub fn on_transition<F>(mut self, callback: F) -> FsmActor<S, D>
+        where F: Fn(&S, &S, &D, ActorCell) + Send + Sync + 'static
+    {
+        self.on_transition.push(Box::new(callback));
+        self
+    }
+
+    /// Sets the callback called when the actor is stopped, with its last state and data.
+    ///
+    /// It is not called when the actor is restarted.
+    pub fn on_termination<F>(mut self, callback: F) -> FsmActor<S, D>
+        where F: Fn(&S, &D) + Send + Sync + 'static
+    {
+        self.on_termination = Some(Box::new(callback));
+        self
+    }
+
+    fn add_handler(mut self,
+                   state: S,
+                   timeout: Option<Duration>,
+                   handler: Handler<S, D>)
+                   -> FsmActor<S, D> {
+        self.handlers.insert(state,
+                             StateHandler {
+                                 handler: handler,
+                                 timeout: timeout,
+                             });
+        self
+    }
+
+    fn cancel_timeout(&mut self) {
+        if let Some((_, cancellable)) = self.timeout.take() {
+            cancellable.cancel();
+        }
+    }
+
+    fn handle(&mut self, event: FsmEvent, context: ActorCell) {
+        // Any event cancels the pending timeout, it is rescheduled by the transition.
+        self.cancel_timeout();
+        let transition = match (self.handlers.get(&self.state), event) {
+            (Some(handler), event) => (handler.handler)(event, &mut self.data, context.clone()),
+            // Messages received in a state without handler are not handled by the actor.
+            (None, FsmEvent::Message(message)) => {
+                self.unhandled(message, context.clone());
+                stay()
+            }
+            (None, FsmEvent::StateTimeout) => stay(),
+        };
+        match transition.next {
+            Next::Stop => {
+                context.kill_me();
+                return;
+            }
+            Next::Stay => {}
+            Next::Goto(next) => {
+                for callback in &self.on_transition {
+                    callback(&self.state, &next, &self.data, context.clone());
+                }
+                self.state = next;
+            }
+        }
+        self.schedule_timeout(transition.timeout, context);
+    }
+
+    fn schedule_timeout(&mut self, timeout: Option<Duration>, context: ActorCell) {
+        let timeout = timeout.or_else(|| {
+            self.handlers.get(&self.state).and_then(|handler| handler.timeout)
+        });
+        if let Some(timeout) = timeout {
+            let id = NEXT_TIMEOUT_ID.fetch_add(1, Ordering::SeqCst);
+            let cancellable = context.schedule_once(timeout,
+                                                    context.actor_ref(),
+                                                    StateTimeoutMessage(id));
+            self.timeout = Some((id, cancellable));
+        }
+    }
+}
+
+impl<S, D> ActorMut for FsmActor<S, D>
+    where S: Clone + Eq + Hash + Send + 'static,
+          D: Send + 'static
+{
+    fn receive(&mut self, message: Box<Any>, context: ActorCell) {
+        match Box::<Any>::downcast::<StateTimeoutMessage>(message) {
+            Ok(timeout) => {
+                // A timeout of a state we left (or that was cancelled) is ignored.
+                let current = self.timeout.as_ref().map(|&(id, _)| id);
+                if current == Some(timeout.0) {
+                    self.handle(FsmEvent::StateTimeout, context);
+                }
+            }
+            Err(message) => self.handle(FsmEvent::Message(message), context),
+        }
+    }
+
+    fn pre_start(&mut self, context: ActorCell) {
+        self.schedule_timeout(None, context);
+    }
+
+    fn post_stop(&mut self) {
+        self.cancel_timeout();
+        if let Some(ref callback) = self.on_termination {
+            callback(&self.state, &self.data);
+        }
+    }
+
+    // The actor is not terminated by a restart, so `on_termination` is not called.
+    fn pre_restart(&mut self, _context: ActorCell) {
+        self.cancel_timeout();
+    }
+}
@@ -15,3 +15,12 @@
 
 /// Actors core.
 pub mod actors;
+
+/// Event sourced actors.
+pub mod persistence;
+
+/// Finite state machine actors.
+pub mod fsm;
+
+/// Tools to test actors.
+pub mod testkit;
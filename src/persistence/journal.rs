@@ -0,0 +1,301 @@
+extern crate eventual;
+
+use self::eventual::Future;
+
+use std::cmp;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use persistence::{encode_file_name, write_atomically};
+
+/// An event as it is stored in a journal.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PersistentRepr {
+    /// Id of the persistent actor that persisted the event.
+    pub persistence_id: String,
+    /// Sequence number of the event, the first event of a persistent actor has number 1.
+    pub sequence_nr: u64,
+    /// Serialized event.
+    pub payload: Vec<u8>,
+}
+
+/// Trait to be implemented by journal plugins.
+///
+/// The operations return futures so that a journal can do its work asynchronously, errors are
+/// described by a String.
+pub trait AsyncJournal: Send + Sync {
+    /// Appends the events to the journal.
+    ///
+    /// The events are either all written or none of them is.
+    fn write_events(&self, events: Vec<PersistentRepr>) -> Future<(), String>;
+
+    /// Gives the events of `persistence_id` with a sequence number of at least
+    /// `from_sequence_nr`, in order.
+    fn replay_events(&self, persistence_id: &str, from_sequence_nr: u64)
+        -> Future<Vec<PersistentRepr>, String>;
+
+    /// Gives the highest sequence number written for `persistence_id`, 0 if there is none.
+    ///
+    /// Deleting events does not change the highest sequence number.
+    fn highest_sequence_nr(&self, persistence_id: &str) -> Future<u64, String>;
+
+    /// Deletes the events of `persistence_id` up to `to_sequence_nr` (included), they will no
+    /// longer be replayed.
+    fn delete_events_to(&self, persistence_id: &str, to_sequence_nr: u64) -> Future<(), String>;
+}
+
+/// Journal keeping the events in memory.
+///
+/// This is mostly useful for tests, as events do not survive the process.
+pub struct InMemoryJournal {
+    journals: Mutex<HashMap<String, InMemoryEvents>>,
+}
+
+#[derive(Default)]
+struct InMemoryEvents {
+    // The last event is kept even when deleted, to remember the highest sequence number.
+    events: Vec<PersistentRepr>,
+    deleted_to: u64,
+}
+
+impl InMemoryJournal {
+    /// Creates an empty journal.
+    pub fn new() -> InMemoryJournal {
+        InMemoryJournal { journals: Mutex::new(HashMap::new()) }
+    }
+}
+
+impl Default for InMemoryJournal {
+    fn default() -> InMemoryJournal {
+        InMemoryJournal::new()
+    }
+}
+
+impl AsyncJournal for InMemoryJournal {
+    fn write_events(&self, events: Vec<PersistentRepr>) -> Future<(), String> {
+        let mut journals = self.journals.lock().unwrap();
+        for event in events {
+            journals.entry(event.persistence_id.clone())
+                    .or_insert_with(InMemoryEvents::default)
+                    .events
+                    .push(event);
+        }
+        Future::of(())
+    }
+
+    fn replay_events(&self, persistence_id: &str, from_sequence_nr: u64)
+        -> Future<Vec<PersistentRepr>, String> {
+        let journals = self.journals.lock().unwrap();
+        let events = match journals.get(persistence_id) {
+            Some(journal) => {
+                journal.events
+                       .iter()
+                       .filter(|e| e.sequence_nr >= from_sequence_nr && e.sequence_nr > journal.deleted_to)
+                       .cloned()
+                       .collect()
+            }
+            None => Vec::new(),
+        };
+        Future::of(events)
+    }
+
+    fn highest_sequence_nr(&self, persistence_id: &str) -> Future<u64, String> {
+        let journals = self.journals.lock().unwrap();
+        let highest = journals.get(persistence_id)
+                              .and_then(|journal| journal.events.last())
+                              .map_or(0, |event| event.sequence_nr);
+        Future::of(highest)
+    }
+
+    fn delete_events_to(&self, persistence_id: &str, to_sequence_nr: u64) -> Future<(), String> {
+        let mut journals = self.journals.lock().unwrap();
+        if let Some(journal) = journals.get_mut(persistence_id) {
+            journal.deleted_to = cmp::max(journal.deleted_to, to_sequence_nr);
+            let last = journal.events.last().map_or(0, |event| event.sequence_nr);
+            let deleted_to = journal.deleted_to;
+            journal.events.retain(|e| e.sequence_nr > deleted_to || e.sequence_nr == last);
+        }
+        Future::of(())
+    }
+}
+
+/// Journal appending the events to files, one file per persistent actor.
+///
+/// Each record is the sequence number (8 bytes), the payload length (4 bytes), both little
+/// endian, followed by the payload. A truncated last record (for example if the process died
+/// while writing it) is ignored.
+///
+/// Deleted events are removed from the file, except the last one which is kept to remember the
+/// highest sequence number, a second file records up to which sequence number events are deleted.
+pub struct FileJournal {
+    directory: PathBuf,
+    // Serializes the accesses to the files.
+    lock: Mutex<()>,
+}
+
+impl FileJournal {
+    /// Creates a journal storing its files in `directory`, which is created if needed.
+    pub fn new(directory: PathBuf) -> Result<FileJournal, String> {
+        fs::create_dir_all(&directory).map_err(|e| e.to_string())?;
+        Ok(FileJournal {
+            directory: directory,
+            lock: Mutex::new(()),
+        })
+    }
+
+    fn file_path(&self, persistence_id: &str) -> PathBuf {
+        self.directory.join(format!("{}.journal", encode_file_name(persistence_id)))
+    }
+
+    fn deleted_to_path(&self, persistence_id: &str) -> PathBuf {
+        self.directory.join(format!("{}.deleted", encode_file_name(persistence_id)))
+    }
+
+    fn deleted_to(&self, persistence_id: &str) -> Result<u64, String> {
+        let path = self.deleted_to_path(persistence_id);
+        if !path.exists() {
+            return Ok(0);
+        }
+        let mut bytes = Vec::new();
+        File::open(path).and_then(|mut file| file.read_to_end(&mut bytes))
+                        .map_err(|e| e.to_string())?;
+        if bytes.len() != 8 {
+            return Err(format!("Corrupted deletion marker for {}", persistence_id));
+        }
+        Ok(bytes_to_u64(&bytes))
+    }
+
+    fn delete_to(&self, persistence_id: &str, to_sequence_nr: u64) -> Result<(), String> {
+        let deleted_to = cmp::max(self.deleted_to(persistence_id)?, to_sequence_nr);
+        // The marker is written first, so that if we crash before the compaction the deleted
+        // events are still not replayed.
+        write_atomically(&self.deleted_to_path(persistence_id), &u64_to_bytes(deleted_to))
+            .map_err(|e| e.to_string())?;
+        let events = self.read(persistence_id)?;
+        let last = events.last().map_or(0, |event| event.sequence_nr);
+        let mut buffer = Vec::new();
+        for event in events.iter().filter(|e| e.sequence_nr > deleted_to || e.sequence_nr == last) {
+            write_record(&mut buffer, event);
+        }
+        write_atomically(&self.file_path(persistence_id), &buffer).map_err(|e| e.to_string())
+    }
+
+    fn append(&self, events: Vec<PersistentRepr>) -> Result<(), String> {
+        // Events are grouped by file so that each file gets a single write.
+        let mut buffers: HashMap<String, Vec<u8>> = HashMap::new();
+        for event in events {
+            let buffer = buffers.entry(event.persistence_id.clone()).or_insert_with(Vec::new);
+            write_record(buffer, &event);
+        }
+        for (persistence_id, buffer) in buffers {
+            let mut file = OpenOptions::new().create(true)
+                                             .append(true)
+                                             .open(self.file_path(&persistence_id))
+                                             .map_err(|e| e.to_string())?;
+            file.write_all(&buffer).map_err(|e| e.to_string())?;
+            file.sync_data().map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    fn read(&self, persistence_id: &str) -> Result<Vec<PersistentRepr>, String> {
+        let path = self.file_path(persistence_id);
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        let mut bytes = Vec::new();
+        File::open(path).and_then(|mut file| file.read_to_end(&mut bytes))
+                        .map_err(|e| e.to_string())?;
+        let mut events = Vec::new();
+        let mut offset = 0;
+        while offset + 12 <= bytes.len() {
+            let sequence_nr = bytes_to_u64(&bytes[offset..offset + 8]);
+            let len = bytes_to_u32(&bytes[offset + 8..offset + 12]) as usize;
+            if offset + 12 + len > bytes.len() {
+                break;
+            }
+            events.push(PersistentRepr {
+                persistence_id: persistence_id.to_owned(),
+                sequence_nr: sequence_nr,
+                payload: bytes[offset + 12..offset + 12 + len].to_vec(),
+            });
+            offset += 12 + len;
+        }
+        Ok(events)
+    }
+}
+
+impl AsyncJournal for FileJournal {
+    fn write_events(&self, events: Vec<PersistentRepr>) -> Future<(), String> {
+        let _lock = self.lock.lock().unwrap();
+        match self.append(events) {
+            Ok(()) => Future::of(()),
+            Err(e) => Future::error(e),
+        }
+    }
+
+    fn replay_events(&self, persistence_id: &str, from_sequence_nr: u64)
+        -> Future<Vec<PersistentRepr>, String> {
+        let _lock = self.lock.lock().unwrap();
+        let events = self.deleted_to(persistence_id).and_then(|deleted_to| {
+            self.read(persistence_id).map(|events| {
+                events.into_iter()
+                      .filter(|e| e.sequence_nr >= from_sequence_nr && e.sequence_nr > deleted_to)
+                      .collect()
+            })
+        });
+        match events {
+            Ok(events) => Future::of(events),
+            Err(e) => Future::error(e),
+        }
+    }
+
+    fn highest_sequence_nr(&self, persistence_id: &str) -> Future<u64, String> {
+        let _lock = self.lock.lock().unwrap();
+        match self.read(persistence_id) {
+            Ok(events) => Future::of(events.last().map_or(0, |event| event.sequence_nr)),
+            Err(e) => Future::error(e),
+        }
+    }
+
+    fn delete_events_to(&self, persistence_id: &str, to_sequence_nr: u64) -> Future<(), String> {
+        let _lock = self.lock.lock().unwrap();
+        match self.delete_to(persistence_id, to_sequence_nr) {
+            Ok(()) => Future::of(()),
+            Err(e) => Future::error(e),
+        }
+    }
+}
+
+fn write_record(buffer: &mut Vec<u8>, event: &PersistentRepr) {
+    buffer.extend_from_slice(&u64_to_bytes(event.sequence_nr));
+    buffer.extend_from_slice(&u32_to_bytes(event.payload.len() as u32));
+    buffer.extend_from_slice(&event.payload);
+}
+
+fn u64_to_bytes(n: u64) -> [u8; 8] {
+    let mut bytes = [0u8; 8];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = (n >> (8 * i)) as u8;
+    }
+    bytes
+}
+
+fn u32_to_bytes(n: u32) -> [u8; 4] {
+    let mut bytes = [0u8; 4];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = (n >> (8 * i)) as u8;
+    }
+    bytes
+}
+
+fn bytes_to_u64(bytes: &[u8]) -> u64 {
+    bytes.iter().rev().fold(0, |acc, byte| (acc << 8) | *byte as u64)
+}
+
+fn bytes_to_u32(bytes: &[u8]) -> u32 {
+    bytes.iter().rev().fold(0, |acc, byte| (acc << 8) | *byte as u32)
+}
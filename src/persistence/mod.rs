@@ -0,0 +1,47 @@
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+
+pub use self::journal::{AsyncJournal, FileJournal, InMemoryJournal, PersistentRepr};
+pub use self::persistent_actor::{Event, EventsDeleted, Persistence, PersistentActor, PersistentProps,
+                                 SnapshotSaved, SnapshotsDeleted};
+pub use self::snapshot::{LocalSnapshotStore, SelectedSnapshot, SnapshotMetadata,
+                         SnapshotSelectionCriteria, SnapshotStore};
+
+/// Module with the journal plugin trait and its implementations.
+pub mod journal;
+
+/// Module with the PersistentActor trait and the machinery used to run it as an Actor.
+pub mod persistent_actor;
+
+/// Module with the snapshot store plugin trait and its implementations.
+pub mod snapshot;
+
+/// Makes a persistence id usable as a file name, characters other than ascii alphanumerics, `-`
+/// and `_` are replaced by `%` followed by their hexadecimal value.
+fn encode_file_name(persistence_id: &str) -> String {
+    let mut res = String::new();
+    for byte in persistence_id.bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' => res.push(byte as char),
+            _ => res.push_str(&format!("%{:02x}", byte)),
+        }
+    }
+    res
+}
+
+/// Writes `bytes` to a temporary file and renames it to `path`, so that readers see either the
+/// old content or the new one, never a partially written file.
+///
+/// The temporary file is `path` with `.tmp` appended, so that files differing only by their
+/// extension do not share it.
+fn write_atomically(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    {
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(bytes)?;
+        file.sync_all()?;
+    }
+    fs::rename(&tmp_path, path)
+}
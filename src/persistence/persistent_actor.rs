@@ -0,0 +1,470 @@
+extern crate eventual;
+extern crate log;
+
+use self::eventual::{Async, AsyncError, Future};
+use self::log::error;
+
+use std::any::Any;
+use std::cmp;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use actors::{Actor, ActorCell, ActorContext, ActorRef, Arguments, InnerMessage, Message};
+use actors::props::{ActorFactory, ActorInstance};
+use persistence::journal::{AsyncJournal, PersistentRepr};
+use persistence::snapshot::{SelectedSnapshot, SnapshotMetadata, SnapshotSelectionCriteria,
+                            SnapshotStore};
+
+/// Trait to be implemented by the events of a PersistentActor, so that they can be stored in a
+/// journal.
+///
+/// Snapshots of the state of the actor are serialized with this trait as well.
+pub trait Event: Message {
+    /// Serializes the event.
+    fn to_bytes(&self) -> Vec<u8>;
+
+    /// Deserializes an event, returns None if the bytes are not a valid event.
+    fn from_bytes(bytes: &[u8]) -> Option<Self>;
+}
+
+impl Event for String {
+    fn to_bytes(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<String> {
+        String::from_utf8(bytes.to_vec()).ok()
+    }
+}
+
+/// This is the trait to implement to become an event sourced actor.
+///
+/// Instead of changing its state directly when receiving a command, a persistent actor persists
+/// events describing the changes and applies them once they are written to the journal. When it
+/// is started (or restarted) all its events are replayed through `receive_recover` before it
+/// handles any new message.
+///
+/// The journal is never waited for on the consumer thread: while the actor recovers or waits for
+/// its events to be written, the commands it receives are stashed and handled afterwards.
+///
+/// If a snapshot store is given, the latest snapshot is offered first to `receive_snapshot` and,
+/// if the actor restores its state from it, only the events persisted after it are replayed.
+///
+/// It is turned into an Actor with `PersistentProps`.
+pub trait PersistentActor: Send + Sync + Sized + 'static {
+    /// Type of the events persisted by the actor.
+    type Event: Event;
+
+    /// Id under which the events are stored, it has to be stable across incarnations of the
+    /// actor.
+    fn persistence_id(&self) -> String;
+
+    /// Handles a regular message, the equivalent of `Actor::receive`.
+    fn receive_command(&self, command: Box<Any>, context: ActorCell, persistence: &Persistence<Self>);
+
+    /// Applies an event that was persisted by a previous incarnation of the actor.
+    fn receive_recover(&self, event: Self::Event, context: ActorCell);
+
+    /// Restores the state from a snapshot saved with `Persistence::save_snapshot`, the snapshot
+    /// can be deserialized with `SelectedSnapshot::get`.
+    ///
+    /// Returns whether the state was restored. By default the snapshot is ignored and all the
+    /// events are replayed from the first one, so this has to be implemented by the actors
+    /// deleting the events covered by their snapshots.
+    fn receive_snapshot(&self, _snapshot: SelectedSnapshot, _context: ActorCell) -> bool {
+        false
+    }
+
+    /// Method called once all the events have been replayed.
+    fn recovery_completed(&self, _context: ActorCell) {}
+
+    /// Method called when the events could not be replayed, the actor is stopped afterwards.
+    fn recovery_failed(&self, _cause: &str, _context: ActorCell) {}
+
+    /// Method called after the Actor is stopped.
+    fn post_stop(&self) {}
+}
+
+/// Handler of a persisted event, called once the event is in the journal.
+type EventHandler<A> = Box<FnOnce(&A, <A as PersistentActor>::Event, ActorCell) + Send>;
+
+static NEXT_PERSISTENCE_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Message sent by the journal callbacks to the persistent actor.
+struct JournalResponse {
+    // Id of the `Persistence` which sent the request, responses to a previous incarnation of the
+    // actor are ignored.
+    persistence: usize,
+    result: JournalResult,
+}
+
+enum JournalResult {
+    /// The latest snapshot, if any.
+    SnapshotLoaded(Result<Option<SelectedSnapshot>, String>),
+    /// The highest sequence number and the events to replay.
+    Replayed(Result<(u64, Vec<PersistentRepr>), String>),
+    /// Outcome of the write of the oldest pending event.
+    Written(Result<(), String>),
+}
+
+/// Message received by a persistent actor once `Persistence::save_snapshot` is done.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SnapshotSaved {
+    /// Metadata of the snapshot.
+    pub metadata: SnapshotMetadata,
+    /// Whether the snapshot was saved.
+    pub result: Result<(), String>,
+}
+
+/// Message received by a persistent actor once `Persistence::delete_snapshots` is done.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SnapshotsDeleted {
+    /// Criteria of the deleted snapshots.
+    pub criteria: SnapshotSelectionCriteria,
+    /// Whether the snapshots were deleted.
+    pub result: Result<(), String>,
+}
+
+/// Message received by a persistent actor once `Persistence::delete_events` is done.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EventsDeleted {
+    /// Sequence number up to which the events were deleted.
+    pub to_sequence_nr: u64,
+    /// Whether the events were deleted.
+    pub result: Result<(), String>,
+}
+
+/// Handle given to a PersistentActor to persist its events.
+pub struct Persistence<A: PersistentActor> {
+    journal: Arc<AsyncJournal>,
+    snapshot_store: Option<Arc<SnapshotStore>>,
+    persistence_id: String,
+    id: usize,
+    // Set when the recovery starts, the journal responses are sent to it.
+    actor_ref: Mutex<Option<ActorRef>>,
+    sequence_nr: Mutex<u64>,
+    recovering: Mutex<bool>,
+    // Events being written with their handlers, in the order of the writes.
+    pending: Mutex<VecDeque<(A::Event, EventHandler<A>)>>,
+}
+
+impl<A: PersistentActor> Persistence<A> {
+    fn new(journal: Arc<AsyncJournal>,
+           snapshot_store: Option<Arc<SnapshotStore>>,
+           persistence_id: String)
+           -> Persistence<A> {
+        Persistence {
+            journal: journal,
+            snapshot_store: snapshot_store,
+            persistence_id: persistence_id,
+            id: NEXT_PERSISTENCE_ID.fetch_add(1, Ordering::SeqCst),
+            actor_ref: Mutex::new(None),
+            sequence_nr: Mutex::new(0),
+            recovering: Mutex::new(true),
+            pending: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Writes the event to the journal and then calls `handler` with the actor, the event and
+    /// the context of the actor.
+    ///
+    /// The handler is where the state of the actor is supposed to be updated. The commands
+    /// received until it has run are stashed, so they see the updated state.
+    ///
+    /// If the event cannot be written the actor is stopped, a new incarnation will recover from
+    /// what is actually in the journal.
+    pub fn persist<F>(&self, event: A::Event, handler: F)
+        where F: FnOnce(&A, A::Event, ActorCell) + Send + 'static
+    {
+        let repr = {
+            let mut sequence_nr = self.sequence_nr.lock().unwrap();
+            *sequence_nr += 1;
+            PersistentRepr {
+                persistence_id: self.persistence_id.clone(),
+                sequence_nr: *sequence_nr,
+                payload: event.to_bytes(),
+            }
+        };
+        self.pending.lock().unwrap().push_back((event, Box::new(handler)));
+        let actor_ref = self.actor_ref.lock().unwrap().clone();
+        let id = self.id;
+        self.journal.write_events(vec![repr]).receive(move |result| {
+            let result = result.map_err(describe);
+            send_response(actor_ref, id, JournalResult::Written(result));
+        });
+    }
+
+    /// Id under which the events are stored.
+    pub fn persistence_id(&self) -> &str {
+        &self.persistence_id
+    }
+
+    /// Sequence number of the last persisted (or replayed) event, this includes the events being
+    /// written.
+    pub fn last_sequence_nr(&self) -> u64 {
+        *self.sequence_nr.lock().unwrap()
+    }
+
+    /// Saves `state` as a snapshot of the actor at the current sequence number, the actor then
+    /// receives a `SnapshotSaved` message.
+    ///
+    /// Saving fails if the actor was not created with a snapshot store.
+    pub fn save_snapshot<S: Event>(&self, state: &S) {
+        let metadata = SnapshotMetadata::new(self.persistence_id.clone(), self.last_sequence_nr());
+        let actor_ref = self.actor_ref.lock().unwrap().clone();
+        let future = match self.snapshot_store() {
+            Ok(store) => store.save(metadata.clone(), state.to_bytes()),
+            Err(e) => Future::error(e),
+        };
+        future.receive(move |result| {
+            let saved = SnapshotSaved {
+                metadata: metadata,
+                result: result.map_err(describe),
+            };
+            send_to(actor_ref, saved);
+        });
+    }
+
+    /// Deletes the snapshots of the actor matching `criteria`, the actor then receives a
+    /// `SnapshotsDeleted` message.
+    ///
+    /// This is typically used after saving a snapshot to only keep the most recent ones.
+    pub fn delete_snapshots(&self, criteria: SnapshotSelectionCriteria) {
+        let actor_ref = self.actor_ref.lock().unwrap().clone();
+        let future = match self.snapshot_store() {
+            Ok(store) => store.delete(&self.persistence_id, criteria),
+            Err(e) => Future::error(e),
+        };
+        future.receive(move |result| {
+            let deleted = SnapshotsDeleted {
+                criteria: criteria,
+                result: result.map_err(describe),
+            };
+            send_to(actor_ref, deleted);
+        });
+    }
+
+    /// Deletes the events of the actor up to `to_sequence_nr` (included), the actor then receives
+    /// an `EventsDeleted` message.
+    ///
+    /// This should only be done for events covered by a snapshot, as they will not be replayed
+    /// anymore.
+    pub fn delete_events(&self, to_sequence_nr: u64) {
+        let actor_ref = self.actor_ref.lock().unwrap().clone();
+        self.journal.delete_events_to(&self.persistence_id, to_sequence_nr).receive(move |result| {
+            let deleted = EventsDeleted {
+                to_sequence_nr: to_sequence_nr,
+                result: result.map_err(describe),
+            };
+            send_to(actor_ref, deleted);
+        });
+    }
+
+    fn snapshot_store(&self) -> Result<&Arc<SnapshotStore>, String> {
+        match self.snapshot_store {
+            Some(ref store) => Ok(store),
+            None => Err(format!("{} has no snapshot store", self.persistence_id)),
+        }
+    }
+
+    /// Returns true while the actor has to stash its commands.
+    fn stashing(&self) -> bool {
+        *self.recovering.lock().unwrap() || !self.pending.lock().unwrap().is_empty()
+    }
+
+    /// Starts the recovery, with the latest snapshot if there is a snapshot store.
+    fn recover(&self, context: ActorCell) {
+        let actor_ref = context.actor_ref();
+        *self.actor_ref.lock().unwrap() = Some(actor_ref.clone());
+        match self.snapshot_store {
+            Some(ref store) => {
+                let id = self.id;
+                store.load_latest(&self.persistence_id, SnapshotSelectionCriteria::latest())
+                     .receive(move |result| {
+                         let result = result.map_err(describe);
+                         send_response(Some(actor_ref), id, JournalResult::SnapshotLoaded(result));
+                     });
+            }
+            None => self.replay(1),
+        }
+    }
+
+    /// Asks the journal for the events to replay, they are applied when the response arrives.
+    fn replay(&self, from_sequence_nr: u64) {
+        let actor_ref = self.actor_ref.lock().unwrap().clone();
+        let journal = self.journal.clone();
+        let persistence_id = self.persistence_id.clone();
+        let id = self.id;
+        self.journal
+            .highest_sequence_nr(&self.persistence_id)
+            .and_then(move |highest| {
+                // Events covered by the snapshot might have been deleted from the journal.
+                let highest = cmp::max(highest, from_sequence_nr - 1);
+                journal.replay_events(&persistence_id, from_sequence_nr)
+                       .map(move |events| (highest, events))
+            })
+            .receive(move |result| {
+                let result = result.map_err(describe);
+                send_response(actor_ref, id, JournalResult::Replayed(result));
+            });
+    }
+
+    /// Handles the response of the journal to one of our requests.
+    fn handle_response(&self, actor: &A, result: JournalResult, context: ActorCell) {
+        match result {
+            JournalResult::SnapshotLoaded(Ok(snapshot)) => {
+                let mut from_sequence_nr = 1;
+                if let Some(snapshot) = snapshot {
+                    let sequence_nr = snapshot.metadata.sequence_nr;
+                    if actor.receive_snapshot(snapshot, context.clone()) {
+                        from_sequence_nr = sequence_nr + 1;
+                    }
+                }
+                self.replay(from_sequence_nr);
+            }
+            JournalResult::SnapshotLoaded(Err(cause)) => self.recovery_failed(actor, &cause, context),
+            JournalResult::Replayed(Ok((highest, events))) => {
+                for repr in events {
+                    match A::Event::from_bytes(&repr.payload) {
+                        Some(event) => actor.receive_recover(event, context.clone()),
+                        None => {
+                            let cause = format!("failed to deserialize event {}", repr.sequence_nr);
+                            return self.recovery_failed(actor, &cause, context);
+                        }
+                    }
+                }
+                *self.sequence_nr.lock().unwrap() = highest;
+                *self.recovering.lock().unwrap() = false;
+                actor.recovery_completed(context.clone());
+                context.unstash_all();
+            }
+            JournalResult::Replayed(Err(cause)) => self.recovery_failed(actor, &cause, context),
+            JournalResult::Written(Ok(())) => {
+                let persisted = self.pending.lock().unwrap().pop_front();
+                if let Some((event, handler)) = persisted {
+                    handler(actor, event, context.clone());
+                }
+                if !self.stashing() {
+                    context.unstash_all();
+                }
+            }
+            JournalResult::Written(Err(cause)) => {
+                error!("Failed to persist an event of {}: {}", self.persistence_id, cause);
+                context.kill_me();
+            }
+        }
+    }
+
+    fn recovery_failed(&self, actor: &A, cause: &str, context: ActorCell) {
+        error!("Failed to recover {}: {}", self.persistence_id, cause);
+        actor.recovery_failed(cause, context.clone());
+        context.kill_me();
+    }
+}
+
+/// Sends a journal response to the persistent actor.
+fn send_response(actor_ref: Option<ActorRef>, id: usize, result: JournalResult) {
+    let response = JournalResponse {
+        persistence: id,
+        result: result,
+    };
+    send_to(actor_ref, response);
+}
+
+/// Sends a message to the persistent actor, with itself as sender.
+fn send_to<M: Any + Send>(actor_ref: Option<ActorRef>, message: M) {
+    if let Some(actor_ref) = actor_ref {
+        actor_ref.receive(InnerMessage::Message(Box::new(message)), actor_ref.clone());
+    }
+}
+
+fn describe(error: AsyncError<String>) -> String {
+    match error {
+        AsyncError::Failed(cause) => cause,
+        AsyncError::Aborted => "the journal aborted the request".to_owned(),
+    }
+}
+
+/// Actor running a PersistentActor.
+struct PersistentActorCell<A: PersistentActor> {
+    actor: A,
+    persistence: Persistence<A>,
+}
+
+impl<A: PersistentActor> Actor for PersistentActorCell<A> {
+    fn receive(&self, message: Box<Any>, context: ActorCell) {
+        match Box::<Any>::downcast::<JournalResponse>(message) {
+            Ok(response) => {
+                if response.persistence == self.persistence.id {
+                    self.persistence.handle_response(&self.actor, response.result, context);
+                }
+            }
+            Err(command) => self.actor.receive_command(command, context, &self.persistence),
+        }
+    }
+
+    fn stashes(&self, message: &Any) -> bool {
+        !message.is::<JournalResponse>() && self.persistence.stashing()
+    }
+
+    // This is also called by post_restart, so recovery is done again when the actor restarts.
+    fn pre_start(&self, context: ActorCell) {
+        self.persistence.recover(context);
+    }
+
+    fn post_stop(&self) {
+        self.actor.post_stop();
+    }
+}
+
+/// Factory for PersistentActors, it is the equivalent of `Props` with the journal to use.
+pub struct PersistentProps<Args: Arguments, A: PersistentActor> {
+    creator: Arc<Fn(Args) -> A + Sync + Send>,
+    args: Args,
+    journal: Arc<AsyncJournal>,
+    snapshot_store: Option<Arc<SnapshotStore>>,
+}
+
+impl<Args: Arguments, A: PersistentActor> PersistentProps<Args, A> {
+    /// Creates a `PersistentProps` which is a factory for `A` with the `creator` function and
+    /// `args` args, that will store its events in `journal`.
+    pub fn new(creator: Arc<Fn(Args) -> A + Sync + Send>,
+               args: Args,
+               journal: Arc<AsyncJournal>)
+               -> Arc<ActorFactory> {
+        Arc::new(PersistentProps::<Args, A> {
+            creator: creator,
+            args: args,
+            journal: journal,
+            snapshot_store: None,
+        })
+    }
+
+    /// Same as `new`, with a snapshot store in which the actor can save snapshots of its state.
+    pub fn with_snapshot_store(creator: Arc<Fn(Args) -> A + Sync + Send>,
+                               args: Args,
+                               journal: Arc<AsyncJournal>,
+                               snapshot_store: Arc<SnapshotStore>)
+                               -> Arc<ActorFactory> {
+        Arc::new(PersistentProps::<Args, A> {
+            creator: creator,
+            args: args,
+            journal: journal,
+            snapshot_store: Some(snapshot_store),
+        })
+    }
+}
+
+impl<Args: Arguments, A: PersistentActor> ActorFactory for PersistentProps<Args, A> {
+    fn create(&self) -> ActorInstance {
+        let actor = (self.creator)(self.args.clone());
+        let persistence = Persistence::new(self.journal.clone(),
+                                           self.snapshot_store.clone(),
+                                           actor.persistence_id());
+        ActorInstance::Shared(Arc::new(PersistentActorCell {
+            actor: actor,
+            persistence: persistence,
+        }))
+    }
+}
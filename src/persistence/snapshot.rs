@@ -0,0 +1,237 @@
+extern crate eventual;
+
+use self::eventual::Future;
+
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use persistence::{encode_file_name, write_atomically};
+use persistence::persistent_actor::Event;
+
+/// Information about a snapshot.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SnapshotMetadata {
+    /// Id of the persistent actor the snapshot belongs to.
+    pub persistence_id: String,
+    /// Sequence number of the last event included in the snapshot.
+    pub sequence_nr: u64,
+    /// Time at which the snapshot was taken, in milliseconds since the unix epoch.
+    pub timestamp: u64,
+}
+
+impl SnapshotMetadata {
+    /// Creates the metadata of a snapshot taken now.
+    pub fn new(persistence_id: String, sequence_nr: u64) -> SnapshotMetadata {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        SnapshotMetadata {
+            persistence_id: persistence_id,
+            sequence_nr: sequence_nr,
+            timestamp: now.as_secs() * 1000 + u64::from(now.subsec_nanos() / 1_000_000),
+        }
+    }
+}
+
+/// A snapshot loaded from a snapshot store.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SelectedSnapshot {
+    /// Information about the snapshot.
+    pub metadata: SnapshotMetadata,
+    /// Serialized state.
+    pub snapshot: Vec<u8>,
+}
+
+impl SelectedSnapshot {
+    /// Deserializes the state, returns None if the bytes are not a valid `S`.
+    pub fn get<S: Event>(&self) -> Option<S> {
+        S::from_bytes(&self.snapshot)
+    }
+}
+
+/// Selects snapshots by sequence number and timestamp, bounds are included.
+///
+/// This is used both to choose the snapshot to recover from and to choose the snapshots to delete.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SnapshotSelectionCriteria {
+    /// Upper bound for the sequence number.
+    pub max_sequence_nr: u64,
+    /// Upper bound for the timestamp.
+    pub max_timestamp: u64,
+    /// Lower bound for the sequence number.
+    pub min_sequence_nr: u64,
+    /// Lower bound for the timestamp.
+    pub min_timestamp: u64,
+}
+
+impl SnapshotSelectionCriteria {
+    /// Selects all the snapshots.
+    pub fn latest() -> SnapshotSelectionCriteria {
+        SnapshotSelectionCriteria {
+            max_sequence_nr: u64::max_value(),
+            max_timestamp: u64::max_value(),
+            min_sequence_nr: 0,
+            min_timestamp: 0,
+        }
+    }
+
+    /// Selects the snapshots up to `max_sequence_nr` (included).
+    pub fn up_to(max_sequence_nr: u64) -> SnapshotSelectionCriteria {
+        SnapshotSelectionCriteria {
+            max_sequence_nr: max_sequence_nr,
+            ..SnapshotSelectionCriteria::latest()
+        }
+    }
+
+    /// Selects no snapshot, recovering with it replays all the events.
+    pub fn none() -> SnapshotSelectionCriteria {
+        SnapshotSelectionCriteria {
+            max_sequence_nr: 0,
+            max_timestamp: 0,
+            min_sequence_nr: 1,
+            min_timestamp: 1,
+        }
+    }
+
+    /// Whether the snapshot with the given metadata is selected.
+    pub fn matches(&self, metadata: &SnapshotMetadata) -> bool {
+        metadata.sequence_nr >= self.min_sequence_nr &&
+        metadata.sequence_nr <= self.max_sequence_nr &&
+        metadata.timestamp >= self.min_timestamp &&
+        metadata.timestamp <= self.max_timestamp
+    }
+}
+
+/// Trait to be implemented by snapshot store plugins.
+///
+/// The operations return futures so that a store can do its work asynchronously, errors are
+/// described by a String.
+pub trait SnapshotStore: Send + Sync {
+    /// Saves a snapshot.
+    fn save(&self, metadata: SnapshotMetadata, snapshot: Vec<u8>) -> Future<(), String>;
+
+    /// Loads the most recent snapshot of `persistence_id` matching `criteria`, if any.
+    fn load_latest(&self, persistence_id: &str, criteria: SnapshotSelectionCriteria)
+        -> Future<Option<SelectedSnapshot>, String>;
+
+    /// Deletes the snapshots of `persistence_id` matching `criteria`.
+    fn delete(&self, persistence_id: &str, criteria: SnapshotSelectionCriteria) -> Future<(), String>;
+}
+
+/// Snapshot store keeping one file per snapshot in a directory.
+///
+/// Files are named `snapshot-<persistence id>-<sequence number>-<timestamp>` and written
+/// atomically, so that a crash while saving never leaves a partial snapshot behind.
+pub struct LocalSnapshotStore {
+    directory: PathBuf,
+    // Serializes the accesses to the files.
+    lock: Mutex<()>,
+}
+
+impl LocalSnapshotStore {
+    /// Creates a store keeping its files in `directory`, which is created if needed.
+    pub fn new(directory: PathBuf) -> Result<LocalSnapshotStore, String> {
+        fs::create_dir_all(&directory).map_err(|e| e.to_string())?;
+        Ok(LocalSnapshotStore {
+            directory: directory,
+            lock: Mutex::new(()),
+        })
+    }
+
+    fn file_path(&self, metadata: &SnapshotMetadata) -> PathBuf {
+        self.directory.join(format!("snapshot-{}-{}-{}",
+                                    encode_file_name(&metadata.persistence_id),
+                                    metadata.sequence_nr,
+                                    metadata.timestamp))
+    }
+
+    /// Gives the metadata of the snapshots of `persistence_id` matching `criteria`, the most
+    /// recent first.
+    fn list(&self, persistence_id: &str, criteria: SnapshotSelectionCriteria)
+        -> Result<Vec<SnapshotMetadata>, String> {
+        let encoded_id = encode_file_name(persistence_id);
+        let mut snapshots = Vec::new();
+        for entry in fs::read_dir(&self.directory).map_err(|e| e.to_string())? {
+            let entry = entry.map_err(|e| e.to_string())?;
+            let file_name = match entry.file_name().into_string() {
+                Ok(file_name) => file_name,
+                Err(_) => continue,
+            };
+            if !file_name.starts_with("snapshot-") || file_name.ends_with(".tmp") {
+                continue;
+            }
+            // The encoded id can contain `-`, so we parse from the end.
+            let mut parts = file_name["snapshot-".len()..].rsplitn(3, '-');
+            let timestamp = parts.next().and_then(|t| t.parse().ok());
+            let sequence_nr = parts.next().and_then(|s| s.parse().ok());
+            match (parts.next(), sequence_nr, timestamp) {
+                (Some(id), Some(sequence_nr), Some(timestamp)) if id == encoded_id => {
+                    let metadata = SnapshotMetadata {
+                        persistence_id: persistence_id.to_owned(),
+                        sequence_nr: sequence_nr,
+                        timestamp: timestamp,
+                    };
+                    if criteria.matches(&metadata) {
+                        snapshots.push(metadata);
+                    }
+                }
+                _ => {}
+            }
+        }
+        snapshots.sort_by(|a, b| (b.sequence_nr, b.timestamp).cmp(&(a.sequence_nr, a.timestamp)));
+        Ok(snapshots)
+    }
+
+    fn load(&self, persistence_id: &str, criteria: SnapshotSelectionCriteria)
+        -> Result<Option<SelectedSnapshot>, String> {
+        for metadata in self.list(persistence_id, criteria)? {
+            let mut bytes = Vec::new();
+            // If a snapshot cannot be read we try the older ones.
+            if File::open(self.file_path(&metadata))
+                   .and_then(|mut file| file.read_to_end(&mut bytes))
+                   .is_ok() {
+                return Ok(Some(SelectedSnapshot {
+                    metadata: metadata,
+                    snapshot: bytes,
+                }));
+            }
+        }
+        Ok(None)
+    }
+
+    fn delete_matching(&self, persistence_id: &str, criteria: SnapshotSelectionCriteria)
+        -> Result<(), String> {
+        for metadata in self.list(persistence_id, criteria)? {
+            fs::remove_file(self.file_path(&metadata)).map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+impl SnapshotStore for LocalSnapshotStore {
+    fn save(&self, metadata: SnapshotMetadata, snapshot: Vec<u8>) -> Future<(), String> {
+        let _lock = self.lock.lock().unwrap();
+        match write_atomically(&self.file_path(&metadata), &snapshot) {
+            Ok(()) => Future::of(()),
+            Err(e) => Future::error(e.to_string()),
+        }
+    }
+
+    fn load_latest(&self, persistence_id: &str, criteria: SnapshotSelectionCriteria)
+        -> Future<Option<SelectedSnapshot>, String> {
+        let _lock = self.lock.lock().unwrap();
+        match self.load(persistence_id, criteria) {
+            Ok(snapshot) => Future::of(snapshot),
+            Err(e) => Future::error(e),
+        }
+    }
+
+    fn delete(&self, persistence_id: &str, criteria: SnapshotSelectionCriteria) -> Future<(), String> {
+        let _lock = self.lock.lock().unwrap();
+        match self.delete_matching(persistence_id, criteria) {
+            Ok(()) => Future::of(()),
+            Err(e) => Future::error(e),
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use actors::{ActorPath, ActorSystem};
+
+/// Runs a scenario many times on deterministic actor systems, each run with a different seed and
+/// thus a different ordering of the messages, and checks invariants after each step.
+///
+/// The scenario is set up by a closure creating the actors and sending the first messages, it
+/// returns whatever state the invariants need to look at (refs, shared counters, channels...).
+/// Then, at each step, one of the actors having a message to handle is picked using the seeded
+/// random generator of the actor system. When no actor has a message to handle, the virtual clock
+/// jumps to the next scheduled task if any, otherwise the run is over.
+///
+/// When an invariant is violated, the seed and the trace (the paths of the actors which handled a
+/// message, in order) are reported. The shortest trace found is reported, so that the failing
+/// ordering is as easy as possible to understand, and it can be replayed with `replay`.
+#[derive(Clone, Copy, Debug)]
+pub struct Explorer {
+    runs: u64,
+    first_seed: u64,
+    max_steps: usize,
+}
+
+/// The report of a run violating an invariant.
+#[derive(Clone, Debug)]
+pub struct InvariantViolation {
+    /// The seed of the run.
+    pub seed: u64,
+    /// Paths of the actors which handled a message, in the order they handled it.
+    pub trace: Vec<Arc<ActorPath>>,
+    /// The error returned by the invariant.
+    pub message: String,
+}
+
+impl fmt::Display for InvariantViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f,
+                 "Invariant violated with seed {} after {} steps: {}",
+                 self.seed,
+                 self.trace.len(),
+                 self.message)?;
+        for (i, path) in self.trace.iter().enumerate() {
+            writeln!(f, "  {}: {}", i, path)?;
+        }
+        Ok(())
+    }
+}
+
+impl Explorer {
+    /// Creates an explorer doing `runs` runs with the seeds starting at 0, each run being stopped
+    /// after 10000 steps.
+    pub fn new(runs: u64) -> Explorer {
+        Explorer {
+            runs: runs,
+            first_seed: 0,
+            max_steps: 10000,
+        }
+    }
+
+    /// Sets the seed of the first run, the following runs use the next seeds.
+    pub fn first_seed(mut self, seed: u64) -> Explorer {
+        self.first_seed = seed;
+        self
+    }
+
+    /// Sets the maximum number of steps of a run, useful for protocols which never go idle.
+    pub fn max_steps(mut self, max_steps: usize) -> Explorer {
+        self.max_steps = max_steps;
+        self
+    }
+
+    /// Does all the runs, returns the violation with the shortest trace (and then the smallest
+    /// seed) if an invariant was violated.
+    pub fn run<S, Setup, Invariant>(&self,
+                                    setup: Setup,
+                                    invariant: Invariant)
+                                    -> Result<(), InvariantViolation>
+        where Setup: Fn(&ActorSystem) -> S,
+              Invariant: Fn(&ActorSystem, &S) -> Result<(), String>
+    {
+        let mut minimal: Option<InvariantViolation> = None;
+        for seed in self.first_seed..self.first_seed + self.runs {
+            if let Err(violation) = self.run_one(seed, &setup, &invariant) {
+                let shorter = match minimal {
+                    Some(ref minimal) => violation.trace.len() < minimal.trace.len(),
+                    None => true,
+                };
+                if shorter {
+                    minimal = Some(violation);
+                }
+            }
+        }
+        match minimal {
+            Some(violation) => Err(violation),
+            None => Ok(()),
+        }
+    }
+
+    /// Does the run with the given seed, typically one reported by `run`, to debug it.
+    pub fn replay<S, Setup, Invariant>(&self,
+                                       seed: u64,
+                                       setup: Setup,
+                                       invariant: Invariant)
+                                       -> Result<(), InvariantViolation>
+        where Setup: Fn(&ActorSystem) -> S,
+              Invariant: Fn(&ActorSystem, &S) -> Result<(), String>
+    {
+        self.run_one(seed, &setup, &invariant)
+    }
+
+    fn run_one<S, Setup, Invariant>(&self,
+                                    seed: u64,
+                                    setup: &Setup,
+                                    invariant: &Invariant)
+                                    -> Result<(), InvariantViolation>
+        where Setup: Fn(&ActorSystem) -> S,
+              Invariant: Fn(&ActorSystem, &S) -> Result<(), String>
+    {
+        let actor_system = ActorSystem::new_deterministic(format!("explorer-{}", seed), seed);
+        // The system actors created with the actor system are not part of the scenario.
+        actor_system.run_until_idle();
+        let state = setup(&actor_system);
+        let mut trace = Vec::new();
+        let mut res = invariant(&actor_system, &state);
+        while res.is_ok() && trace.len() < self.max_steps {
+            match actor_system.step() {
+                Some(path) => trace.push(path),
+                None => {
+                    let scheduler = actor_system.scheduler();
+                    match scheduler.next_deadline() {
+                        Some(deadline) => {
+                            let now = scheduler.now();
+                            scheduler.advance_time(if deadline > now {
+                                deadline - now
+                            } else {
+                                Duration::from_millis(0)
+                            });
+                        }
+                        None => break,
+                    }
+                }
+            }
+            res = invariant(&actor_system, &state);
+        }
+        actor_system.shutdown();
+        res.map_err(|message| {
+            InvariantViolation {
+                seed: seed,
+                trace: trace,
+                message: message,
+            }
+        })
+    }
+}
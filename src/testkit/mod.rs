@@ -0,0 +1,240 @@
+use std::any::Any;
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use actors::{ActorPath, ActorRef, ControlMessage, InnerMessage, Message, MessageReceiver,
+             SystemMessage};
+
+pub use self::explorer::{Explorer, InvariantViolation};
+
+/// Module with the exploration of the message orderings of a scenario.
+pub mod explorer;
+
+// Used to give a unique path to each probe.
+static NEXT_PROBE_ID: AtomicUsize = AtomicUsize::new(0);
+
+type AutoPilot = Box<FnMut(&ActorRef, &Any, &ActorRef) -> bool + Send>;
+
+/// A received message and its sender.
+struct Received {
+    message: Box<Any + Send>,
+    sender: ActorRef,
+}
+
+struct ProbeQueue {
+    messages: Mutex<VecDeque<Received>>,
+    condvar: Condvar,
+    last_sender: Mutex<Option<ActorRef>>,
+    auto_pilot: Mutex<Option<AutoPilot>>,
+}
+
+impl MessageReceiver for ProbeQueue {
+    fn receive(&self, message: InnerMessage, sender: ActorRef, probe: &ActorRef) {
+        let message: Box<Any + Send> = match message {
+            InnerMessage::Message(message) => message,
+            InnerMessage::Control(message) => Box::new(message),
+        };
+        // The auto pilot is taken out while it runs, so that it can send messages to the probe.
+        let auto_pilot = self.auto_pilot.lock().unwrap().take();
+        if let Some(mut pilot) = auto_pilot {
+            if pilot(&sender, &*message, probe) {
+                let mut auto_pilot = self.auto_pilot.lock().unwrap();
+                // A new auto pilot may have been set meanwhile.
+                if auto_pilot.is_none() {
+                    *auto_pilot = Some(pilot);
+                }
+            }
+        }
+        self.messages.lock().unwrap().push_back(Received {
+            message: message,
+            sender: sender,
+        });
+        self.condvar.notify_all();
+    }
+}
+
+/// A TestProbe is an ActorRef that records the messages it receives, so that tests can make
+/// assertions on them.
+///
+/// Messages are taken from the probe in the order they were received, the `expect_*` methods
+/// panic when the expectation is not met, which makes the test fail.
+///
+/// Termination notices of actors watched with `watch` are recorded as `ControlMessage`s.
+pub struct TestProbe {
+    actor_ref: ActorRef,
+    queue: Arc<ProbeQueue>,
+}
+
+impl TestProbe {
+    /// Creates a new probe.
+    pub fn new() -> TestProbe {
+        let queue = Arc::new(ProbeQueue {
+            messages: Mutex::new(VecDeque::new()),
+            condvar: Condvar::new(),
+            last_sender: Mutex::new(None),
+            auto_pilot: Mutex::new(None),
+        });
+        let id = NEXT_PROBE_ID.fetch_add(1, Ordering::SeqCst);
+        let path = ActorPath::new_local(format!("/system/testProbe-{}", id));
+        let actor_ref = ActorRef::with_receiver(queue.clone(), path);
+        TestProbe {
+            actor_ref: actor_ref,
+            queue: queue,
+        }
+    }
+
+    /// Gives the ActorRef of the probe.
+    pub fn actor_ref(&self) -> ActorRef {
+        self.actor_ref.clone()
+    }
+
+    /// Sends a message to `to`, with the probe as sender.
+    pub fn send<MessageTo: Message>(&self, to: ActorRef, message: MessageTo) {
+        self.actor_ref.tell_to(to, message);
+    }
+
+    /// Sends a message to the sender of the last message taken from the probe.
+    pub fn reply<MessageTo: Message>(&self, message: MessageTo) {
+        match self.last_sender() {
+            Some(sender) => self.send(sender, message),
+            None => panic!("The probe has not received any message to reply to."),
+        }
+    }
+
+    /// Sender of the last message taken from the probe.
+    pub fn last_sender(&self) -> Option<ActorRef> {
+        self.queue.last_sender.lock().unwrap().clone()
+    }
+
+    /// Takes the next message received by the probe, waiting at most `timeout`.
+    pub fn receive_one(&self, timeout: Duration) -> Option<Box<Any + Send>> {
+        let deadline = Instant::now() + timeout;
+        let mut messages = self.queue.messages.lock().unwrap();
+        loop {
+            if let Some(received) = messages.pop_front() {
+                *self.queue.last_sender.lock().unwrap() = Some(received.sender);
+                return Some(received.message);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return None;
+            }
+            messages = self.queue.condvar.wait_timeout(messages, deadline - now).unwrap().0;
+        }
+    }
+
+    /// Expects a message of type `T` within `timeout` and gives it.
+    pub fn expect_msg<T: Any>(&self, timeout: Duration) -> T {
+        match self.receive_one(timeout) {
+            Some(message) => {
+                match message.downcast::<T>() {
+                    Ok(message) => *message,
+                    Err(_) => panic!("The probe received a message of an unexpected type."),
+                }
+            }
+            None => panic!("The probe did not receive any message within {:?}.", timeout),
+        }
+    }
+
+    /// Expects that no message is received during `duration`.
+    pub fn expect_no_msg(&self, duration: Duration) {
+        if self.receive_one(duration).is_some() {
+            panic!("The probe received a message when it should not have.");
+        }
+    }
+
+    /// Takes the received messages as long as `f` gives a result for them, for at most `max`.
+    ///
+    /// The first message for which `f` gives None is left in the probe.
+    pub fn receive_while<T, F>(&self, max: Duration, mut f: F) -> Vec<T>
+        where F: FnMut(&Any) -> Option<T>
+    {
+        let deadline = Instant::now() + max;
+        let mut res = Vec::new();
+        let mut messages = self.queue.messages.lock().unwrap();
+        loop {
+            let value = match messages.front() {
+                Some(received) => f(&*received.message),
+                None => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return res;
+                    }
+                    messages = self.queue.condvar.wait_timeout(messages, deadline - now).unwrap().0;
+                    continue;
+                }
+            };
+            match value {
+                Some(value) => {
+                    let received = messages.pop_front().unwrap();
+                    *self.queue.last_sender.lock().unwrap() = Some(received.sender);
+                    res.push(value);
+                }
+                None => return res,
+            }
+        }
+    }
+
+    /// Takes messages until `f` returns true for one of them and gives that one, messages for
+    /// which `f` returns false are dropped.
+    ///
+    /// This panics if no such message is received within `timeout`.
+    pub fn fish_for_message<F>(&self, timeout: Duration, mut f: F) -> Box<Any + Send>
+        where F: FnMut(&Any) -> bool
+    {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let now = Instant::now();
+            let remaining = if deadline > now {
+                deadline - now
+            } else {
+                Duration::from_millis(0)
+            };
+            match self.receive_one(remaining) {
+                Some(message) => {
+                    if f(&*message) {
+                        return message;
+                    }
+                }
+                None => panic!("The probe did not receive the expected message within {:?}.", timeout),
+            }
+        }
+    }
+
+    /// Monitors `actor_ref`, a termination notice will be received by the probe when it stops.
+    pub fn watch(&self, actor_ref: &ActorRef) {
+        actor_ref.receive_system_message(SystemMessage::Watch(self.actor_ref.clone()));
+    }
+
+    /// Stops monitoring `actor_ref`.
+    pub fn unwatch(&self, actor_ref: &ActorRef) {
+        actor_ref.receive_system_message(SystemMessage::Unwatch(self.actor_ref.clone()));
+    }
+
+    /// Expects the termination notice of `actor_ref` within `timeout`, it must have been watched.
+    pub fn expect_terminated(&self, actor_ref: &ActorRef, timeout: Duration) {
+        match self.expect_msg::<ControlMessage>(timeout) {
+            ControlMessage::Terminated(ref terminated) if terminated == actor_ref => {}
+            _ => panic!("The probe did not receive the termination of {}.", actor_ref),
+        }
+    }
+
+    /// Sets an auto pilot, called with the sender, the message and the probe's ref for each
+    /// message received, before it is recorded. It is typically used to reply automatically.
+    ///
+    /// It keeps running as long as it returns true. Messages it sends to the probe itself are
+    /// received while it runs, so they do not go through it.
+    pub fn set_auto_pilot<F>(&self, auto_pilot: F)
+        where F: FnMut(&ActorRef, &Any, &ActorRef) -> bool + Send + 'static
+    {
+        *self.queue.auto_pilot.lock().unwrap() = Some(Box::new(auto_pilot));
+    }
+}
+
+impl Default for TestProbe {
+    fn default() -> TestProbe {
+        TestProbe::new()
+    }
+}
@@ -1,14 +1,30 @@
 extern crate eventual;
+#[macro_use]
 extern crate robots;
+#[cfg(feature = "tokio")]
+extern crate tokio;
 
-use eventual::Async;
+use eventual::{Async, Future};
 
 use std::any::Any;
+use std::future::Future as StdFuture;
+use std::pin::Pin;
 use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::mpsc::{channel, Sender};
+use std::task::{Context, Poll};
 use std::time::Duration;
 
-use robots::actors::{Actor, ActorSystem, ActorCell, ActorContext, ActorRef, Props};
+use robots::actors::{Actor, ActorMut, ActorSystem, ActorCell, ActorContext, ActorCreationError,
+                     ActorRef, ActorState, AskTimeout, AsyncActor, AsyncProps, BlockingAskPolicy,
+                     BoxFuture, ControlMessage, Dispatch, EventualFuture, Extension, InnerMessage,
+                     MutProps, Props, ReplyTo, SlowReceive, Status, UnhandledMessage, pipe_to};
+use robots::fsm::{FsmActor, FsmEvent, goto, stay, stop};
+use robots::persistence::{AsyncJournal, EventsDeleted, FileJournal, InMemoryJournal,
+                          LocalSnapshotStore, Persistence, PersistentActor, PersistentProps,
+                          PersistentRepr, SelectedSnapshot, SnapshotMetadata, SnapshotSaved,
+                          SnapshotStore};
+use robots::testkit::{Explorer, TestProbe};
 
 #[derive(Debug, PartialEq)]
 enum Res {
@@ -72,8 +88,8 @@ fn read_messages_in_order() {
     let tx = Arc::new(Mutex::new(tx));
 
     let props = Props::new(Arc::new(InternalState::new), tx);
-    let actor_ref_1 = actor_system.actor_of(props.clone(), "sender".to_owned());
-    let actor_ref_2 = actor_system.actor_of(props.clone(), "receiver".to_owned());
+    let actor_ref_1 = actor_system.actor_of(props.clone(), "sender".to_owned()).unwrap();
+    let actor_ref_2 = actor_system.actor_of(props.clone(), "receiver".to_owned()).unwrap();
 
     for i in 1..1001 {
         actor_ref_1.tell_to(actor_ref_2.clone(), InternalStateMessage::Set(i as u32));
@@ -94,8 +110,8 @@ fn recover_from_panic() {
     let tx = Arc::new(Mutex::new(tx));
 
     let props = Props::new(Arc::new(InternalState::new), tx);
-    let requester = actor_system.actor_of(props.clone(), "sender".to_owned());
-    let answerer = actor_system.actor_of(props.clone(), "receiver".to_owned());
+    let requester = actor_system.actor_of(props.clone(), "sender".to_owned()).unwrap();
+    let answerer = actor_system.actor_of(props.clone(), "receiver".to_owned()).unwrap();
 
     requester.tell_to(answerer.clone(), InternalStateMessage::Set(10));
     let res = answerer.ask(InternalStateMessage::Get).await().unwrap();
@@ -135,8 +151,8 @@ fn resolve_name_real_path() {
     actor_system.spawn_threads(2);
 
     let props = Props::new(Arc::new(Resolver::new), ());
-    let answerer = actor_system.actor_of(props.clone(), "answerer".to_owned());
-    let requester = actor_system.actor_of(props.clone(), "sender".to_owned());
+    let answerer = actor_system.actor_of(props.clone(), "answerer".to_owned()).unwrap();
+    let requester = actor_system.actor_of(props.clone(), "sender".to_owned()).unwrap();
 
     // We wait to be sure that the actors will be registered to the name resolver.
     std::thread::sleep(Duration::from_millis(100));
@@ -154,7 +170,7 @@ fn resolve_name_fake_path() {
     actor_system.spawn_threads(2);
 
     let props = Props::new(Arc::new(Resolver::new), ());
-    let answerer = actor_system.actor_of(props.clone(), "answerer".to_owned());
+    let answerer = actor_system.actor_of(props.clone(), "answerer".to_owned()).unwrap();
 
     // We wait to be sure that the actors will be registered to the name resolver.
     std::thread::sleep(Duration::from_millis(100));
@@ -178,8 +194,8 @@ struct DoubleAnswer {
 
 impl Actor for DoubleAnswer {
     fn post_restart(&self, _context: ActorCell) {
-        let mut sender = self.sender.lock().unwrap();
-        sender.send(());
+        let sender = self.sender.lock().unwrap();
+        let _ = sender.send(());
     }
 
     fn receive(&self, _message: Box<Any>, context: ActorCell) {
@@ -205,7 +221,7 @@ fn ask_answer_twice() {
     let tx = Arc::new(Mutex::new(tx));
 
     let props = Props::new(Arc::new(DoubleAnswer::new), tx);
-    let answerer = actor_system.actor_of(props.clone(), "answerer".to_owned());
+    let answerer = actor_system.actor_of(props.clone(), "answerer".to_owned()).unwrap();
 
     let res = answerer.ask(()).await().unwrap();
     let res = *Box::<Any>::downcast::<()>(res).unwrap();
@@ -220,3 +236,1516 @@ fn ask_answer_twice() {
 
     actor_system.shutdown();
 }
+
+struct Dummy;
+
+impl Actor for Dummy {
+    fn receive(&self, _message: Box<Any>, _context: ActorCell) {}
+}
+
+impl Dummy {
+    fn new(_dummy: ()) -> Dummy {
+        Dummy
+    }
+}
+
+#[test]
+fn reject_duplicate_names() {
+    let actor_system = ActorSystem::new("test".to_owned());
+
+    let props = Props::new(Arc::new(Dummy::new), ());
+    let _worker = actor_system.actor_of(props.clone(), "worker".to_owned()).unwrap();
+
+    match actor_system.actor_of(props.clone(), "worker".to_owned()) {
+        Err(ActorCreationError::ActorAlreadyExists(path)) => {
+            assert_eq!("/user/worker", *path.logical_path())
+        }
+        _ => panic!("Two actors with the same name were created."),
+    };
+
+    match actor_system.actor_of(props.clone(), "$worker".to_owned()) {
+        Err(ActorCreationError::InvalidActorName(_)) => {}
+        _ => panic!("An actor with a reserved name was created."),
+    };
+
+    actor_system.shutdown();
+}
+
+#[test]
+fn reject_duplicate_system_names() {
+    let actor_system = ActorSystem::new("test".to_owned());
+
+    let props = Props::new(Arc::new(Dummy::new), ());
+    match actor_system.system_actor_of(props.clone(), "name_resolver".to_owned()) {
+        Err(ActorCreationError::ActorAlreadyExists(path)) => {
+            assert_eq!("/system/name_resolver", *path.logical_path())
+        }
+        _ => panic!("Two system actors with the same name were created."),
+    };
+
+    // User and system actors do not share their names.
+    assert!(actor_system.actor_of(props, "name_resolver".to_owned()).is_ok());
+
+    actor_system.shutdown();
+}
+
+#[test]
+fn anonymous_actor_names() {
+    let actor_system = ActorSystem::new("test".to_owned());
+
+    let props = Props::new(Arc::new(Dummy::new), ());
+    let names: Vec<String> = (0..28)
+        .map(|_| actor_system.actor_of_anonymous(props.clone()).path().logical_path().clone())
+        .collect();
+    assert_eq!("/user/$a", names[0]);
+    assert_eq!("/user/$b", names[1]);
+    assert_eq!("/user/$z", names[25]);
+    assert_eq!("/user/$aa", names[26]);
+    assert_eq!("/user/$ab", names[27]);
+
+    // The generated names can not be taken by named actors.
+    match actor_system.actor_of(props, "$c".to_owned()) {
+        Err(ActorCreationError::InvalidActorName(name)) => assert_eq!("$c", name),
+        _ => panic!("An actor took the name of an anonymous actor."),
+    };
+
+    actor_system.shutdown();
+}
+
+#[test]
+fn new_incarnation_does_not_get_old_messages() {
+    let actor_system = ActorSystem::new("test".to_owned());
+    actor_system.spawn_threads(1);
+
+    let (tx, rx) = channel();
+    let tx = Arc::new(Mutex::new(tx));
+
+    let props = Props::new(Arc::new(InternalState::new), tx);
+    let old = actor_system.actor_of(props.clone(), "worker".to_owned()).unwrap();
+    old.receive(InnerMessage::Control(ControlMessage::PoisonPill), old.clone());
+
+    // We wait to be sure that the actor is stopped.
+    std::thread::sleep(Duration::from_millis(100));
+
+    let new = actor_system.actor_of(props.clone(), "worker".to_owned()).unwrap();
+    assert_eq!(old.path(), new.path());
+    assert!(old != new);
+
+    // This goes to the dead letters.
+    new.tell_to(old.clone(), InternalStateMessage::Set(1000));
+    assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+
+    new.tell_to(new.clone(), InternalStateMessage::Set(1000));
+    assert_eq!(Ok(Res::Ok), rx.recv());
+
+    actor_system.shutdown();
+}
+
+// This actor answers with the ref of the sender of the message.
+struct SenderEcho;
+
+impl Actor for SenderEcho {
+    fn receive(&self, _message: Box<Any>, context: ActorCell) {
+        context.tell(context.sender(), context.sender());
+    }
+}
+
+impl SenderEcho {
+    fn new(_dummy: ()) -> SenderEcho {
+        SenderEcho
+    }
+}
+
+#[test]
+fn future_refs_are_distinct() {
+    let actor_system = ActorSystem::new("test".to_owned());
+    actor_system.spawn_threads(1);
+
+    let props = Props::new(Arc::new(SenderEcho::new), ());
+    let echo = actor_system.actor_of(props, "echo".to_owned()).unwrap();
+
+    let first = *Box::<Any>::downcast::<ActorRef>(echo.ask(()).await().unwrap()).unwrap();
+    let second = *Box::<Any>::downcast::<ActorRef>(echo.ask(()).await().unwrap()).unwrap();
+    assert_eq!(first.path(), second.path());
+    assert!(first.uid() != 0);
+    assert!(first != second);
+
+    actor_system.shutdown();
+}
+
+#[derive(Clone)]
+enum ListMessage {
+    Add(String),
+    Get,
+    Panic,
+    Snapshot,
+}
+
+// This actor persists the strings it is sent and gives them back when asked.
+struct PersistentList {
+    id: String,
+    items: Mutex<Vec<String>>,
+    // Sender of the Snapshot message, answered once the events covered by the snapshot are
+    // deleted.
+    snapshot_requester: Mutex<Option<ActorRef>>,
+    restores_snapshots: bool,
+}
+
+impl PersistentActor for PersistentList {
+    type Event = String;
+
+    fn persistence_id(&self) -> String {
+        self.id.clone()
+    }
+
+    fn receive_command(&self,
+                       command: Box<Any>,
+                       context: ActorCell,
+                       persistence: &Persistence<PersistentList>) {
+        let command = match Box::<Any>::downcast::<ListMessage>(command) {
+            Ok(command) => command,
+            Err(command) => {
+                if let Some(saved) = command.downcast_ref::<SnapshotSaved>() {
+                    saved.result.clone().unwrap();
+                    persistence.delete_events(saved.metadata.sequence_nr);
+                } else if let Some(deleted) = command.downcast_ref::<EventsDeleted>() {
+                    deleted.result.clone().unwrap();
+                    let requester = self.snapshot_requester.lock().unwrap().take().unwrap();
+                    context.tell(requester, ());
+                }
+                return;
+            }
+        };
+        match *command {
+            ListMessage::Add(item) => {
+                persistence.persist(item, |list, item, _| list.items.lock().unwrap().push(item))
+            }
+            ListMessage::Get => context.tell(context.sender(), self.items.lock().unwrap().clone()),
+            ListMessage::Panic => panic!(""),
+            ListMessage::Snapshot => {
+                *self.snapshot_requester.lock().unwrap() = Some(context.sender());
+                persistence.save_snapshot(&self.items.lock().unwrap().join("\n"));
+            }
+        }
+    }
+
+    fn receive_recover(&self, event: String, _context: ActorCell) {
+        self.items.lock().unwrap().push(event);
+    }
+
+    fn receive_snapshot(&self, snapshot: SelectedSnapshot, _context: ActorCell) -> bool {
+        if !self.restores_snapshots {
+            return false;
+        }
+        let items = snapshot.get::<String>().unwrap();
+        *self.items.lock().unwrap() = items.split('\n').map(|item| item.to_owned()).collect();
+        true
+    }
+}
+
+impl PersistentList {
+    fn new(id: String) -> PersistentList {
+        PersistentList {
+            id: id,
+            items: Mutex::new(Vec::new()),
+            snapshot_requester: Mutex::new(None),
+            restores_snapshots: true,
+        }
+    }
+
+    // The same list, ignoring the snapshots.
+    fn ignoring_snapshots(id: String) -> PersistentList {
+        PersistentList { restores_snapshots: false, ..PersistentList::new(id) }
+    }
+}
+
+fn get_list(actor_ref: &ActorRef) -> Vec<String> {
+    let res = actor_ref.ask(ListMessage::Get).await().unwrap();
+    *Box::<Any>::downcast::<Vec<String>>(res).unwrap()
+}
+
+#[test]
+fn persistent_actor_recovers_after_restart() {
+    let actor_system = ActorSystem::new("test".to_owned());
+    actor_system.spawn_threads(1);
+
+    let journal = Arc::new(InMemoryJournal::new());
+    let props = PersistentProps::new(Arc::new(PersistentList::new), "list".to_owned(), journal);
+    let list = actor_system.actor_of(props, "list".to_owned()).unwrap();
+
+    list.tell_to(list.clone(), ListMessage::Add("a".to_owned()));
+    list.tell_to(list.clone(), ListMessage::Add("b".to_owned()));
+    assert_eq!(vec!["a".to_owned(), "b".to_owned()], get_list(&list));
+
+    // The restarted actor replays its events before answering.
+    list.tell_to(list.clone(), ListMessage::Panic);
+    assert_eq!(vec!["a".to_owned(), "b".to_owned()], get_list(&list));
+
+    actor_system.shutdown();
+}
+
+#[test]
+fn file_journal_survives_the_actor() {
+    let directory = std::env::temp_dir().join(format!("robots-journal-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&directory);
+
+    let actor_system = ActorSystem::new("test".to_owned());
+    actor_system.spawn_threads(1);
+
+    let journal = Arc::new(FileJournal::new(directory.clone()).unwrap());
+    let props = PersistentProps::new(Arc::new(PersistentList::new), "list/1".to_owned(), journal);
+    let list = actor_system.actor_of(props, "first".to_owned()).unwrap();
+    list.tell_to(list.clone(), ListMessage::Add("a".to_owned()));
+    assert_eq!(vec!["a".to_owned()], get_list(&list));
+
+    // A new journal on the same directory sees the events written by the first one.
+    let journal = Arc::new(FileJournal::new(directory.clone()).unwrap());
+    let props = PersistentProps::new(Arc::new(PersistentList::new), "list/1".to_owned(), journal);
+    let list = actor_system.actor_of(props, "second".to_owned()).unwrap();
+    list.tell_to(list.clone(), ListMessage::Add("b".to_owned()));
+    assert_eq!(vec!["a".to_owned(), "b".to_owned()], get_list(&list));
+
+    actor_system.shutdown();
+    let _ = std::fs::remove_dir_all(&directory);
+}
+
+// Journal which fails to replay the events.
+struct BrokenJournal;
+
+impl AsyncJournal for BrokenJournal {
+    fn write_events(&self, _events: Vec<PersistentRepr>) -> Future<(), String> {
+        Future::of(())
+    }
+
+    fn replay_events(&self, _persistence_id: &str, _from_sequence_nr: u64)
+        -> Future<Vec<PersistentRepr>, String> {
+        Future::error("broken journal".to_owned())
+    }
+
+    fn highest_sequence_nr(&self, _persistence_id: &str) -> Future<u64, String> {
+        Future::of(0)
+    }
+
+    fn delete_events_to(&self, _persistence_id: &str, _to_sequence_nr: u64) -> Future<(), String> {
+        Future::of(())
+    }
+}
+
+// This actor reports its recovery failure and its termination.
+struct FailedRecovery {
+    sender: Arc<Mutex<Sender<String>>>,
+}
+
+impl PersistentActor for FailedRecovery {
+    type Event = String;
+
+    fn persistence_id(&self) -> String {
+        "failed".to_owned()
+    }
+
+    fn receive_command(&self,
+                       _command: Box<Any>,
+                       _context: ActorCell,
+                       _persistence: &Persistence<FailedRecovery>) {
+    }
+
+    fn receive_recover(&self, _event: String, _context: ActorCell) {}
+
+    fn recovery_failed(&self, cause: &str, _context: ActorCell) {
+        let _ = self.sender.lock().unwrap().send(cause.to_owned());
+    }
+
+    fn post_stop(&self) {
+        let _ = self.sender.lock().unwrap().send("stopped".to_owned());
+    }
+}
+
+impl FailedRecovery {
+    fn new(sender: Arc<Mutex<Sender<String>>>) -> FailedRecovery {
+        FailedRecovery { sender: sender }
+    }
+}
+
+#[test]
+fn persistent_actor_stops_when_recovery_fails() {
+    let actor_system = ActorSystem::new("test".to_owned());
+    actor_system.spawn_threads(1);
+
+    let (tx, rx) = channel();
+    let props = PersistentProps::new(Arc::new(FailedRecovery::new),
+                                     Arc::new(Mutex::new(tx)),
+                                     Arc::new(BrokenJournal));
+    actor_system.actor_of(props, "failed".to_owned()).unwrap();
+
+    assert_eq!(Ok("broken journal".to_owned()), rx.recv_timeout(Duration::from_secs(1)));
+    assert_eq!(Ok("stopped".to_owned()), rx.recv_timeout(Duration::from_secs(1)));
+    // The actor is not restarted.
+    assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+
+    actor_system.shutdown();
+}
+
+#[test]
+fn recover_from_snapshot() {
+    let directory = std::env::temp_dir().join(format!("robots-snapshots-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&directory);
+
+    let actor_system = ActorSystem::new("test".to_owned());
+    actor_system.spawn_threads(1);
+
+    let journal = Arc::new(FileJournal::new(directory.join("journal")).unwrap());
+    let snapshots = Arc::new(LocalSnapshotStore::new(directory.join("snapshots")).unwrap());
+    let props = PersistentProps::with_snapshot_store(Arc::new(PersistentList::new),
+                                                     "list".to_owned(),
+                                                     journal.clone(),
+                                                     snapshots.clone());
+    let list = actor_system.actor_of(props.clone(), "first".to_owned()).unwrap();
+    list.tell_to(list.clone(), ListMessage::Add("a".to_owned()));
+    list.tell_to(list.clone(), ListMessage::Add("b".to_owned()));
+    list.ask(ListMessage::Snapshot).await().unwrap();
+    list.tell_to(list.clone(), ListMessage::Add("c".to_owned()));
+    assert_eq!(vec!["a".to_owned(), "b".to_owned(), "c".to_owned()], get_list(&list));
+
+    // The events covered by the snapshot were deleted, so "a" and "b" can only come from it.
+    let list = actor_system.actor_of(props, "second".to_owned()).unwrap();
+    assert_eq!(vec!["a".to_owned(), "b".to_owned(), "c".to_owned()], get_list(&list));
+
+    actor_system.shutdown();
+    let _ = std::fs::remove_dir_all(&directory);
+}
+
+#[test]
+fn snapshots_can_be_ignored() {
+    let directory = std::env::temp_dir().join(format!("robots-ignored-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&directory);
+
+    let actor_system = ActorSystem::new("test".to_owned());
+    actor_system.spawn_threads(1);
+
+    let journal = Arc::new(InMemoryJournal::new());
+    let snapshots = Arc::new(LocalSnapshotStore::new(directory.clone()).unwrap());
+    let props = PersistentProps::with_snapshot_store(Arc::new(PersistentList::ignoring_snapshots),
+                                                     "list".to_owned(),
+                                                     journal,
+                                                     snapshots.clone());
+    let list = actor_system.actor_of(props.clone(), "first".to_owned()).unwrap();
+    list.tell_to(list.clone(), ListMessage::Add("a".to_owned()));
+    list.tell_to(list.clone(), ListMessage::Add("b".to_owned()));
+    assert_eq!(vec!["a".to_owned(), "b".to_owned()], get_list(&list));
+
+    // The snapshot is ignored, all the events are replayed.
+    let metadata = SnapshotMetadata::new("list".to_owned(), 2);
+    snapshots.save(metadata, b"ignored".to_vec()).await().unwrap();
+    let list = actor_system.actor_of(props, "second".to_owned()).unwrap();
+    assert_eq!(vec!["a".to_owned(), "b".to_owned()], get_list(&list));
+
+    actor_system.shutdown();
+    let _ = std::fs::remove_dir_all(&directory);
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+enum DoorState {
+    Closed,
+    Open,
+}
+
+// A door that closes itself if nobody goes through it for 50ms, it counts the people going
+// through and sends its transitions through a channel.
+fn door(sender: Arc<Mutex<Sender<(DoorState, DoorState, u32)>>>) -> FsmActor<DoorState, u32> {
+    FsmActor::new(DoorState::Closed, 0)
+        .when(DoorState::Closed, |_event, _count, _context| goto(DoorState::Open))
+        .when_with_timeout(DoorState::Open, Duration::from_millis(50), |event, count, _context| {
+            match event {
+                FsmEvent::StateTimeout => goto(DoorState::Closed),
+                FsmEvent::Message(_) => {
+                    *count += 1;
+                    stay()
+                }
+            }
+        })
+        .on_transition(move |from, to, count, _context| {
+            let _ = sender.lock().unwrap().send((*from, *to, *count));
+        })
+}
+
+#[test]
+fn fsm_state_timeout() {
+    let actor_system = ActorSystem::new("test".to_owned());
+    actor_system.spawn_threads(1);
+
+    let (tx, rx) = channel();
+    let tx = Arc::new(Mutex::new(tx));
+
+    let props = MutProps::new(Arc::new(door), tx);
+    let door = actor_system.actor_of(props, "door".to_owned()).unwrap();
+
+    door.tell_to(door.clone(), ());
+    assert_eq!(Ok((DoorState::Closed, DoorState::Open, 0)), rx.recv());
+    door.tell_to(door.clone(), ());
+    door.tell_to(door.clone(), ());
+    assert_eq!(Ok((DoorState::Open, DoorState::Closed, 2)), rx.recv());
+
+    actor_system.shutdown();
+}
+
+#[test]
+fn deterministic_fsm_state_timeout() {
+    let actor_system = ActorSystem::new_deterministic("test".to_owned(), 42);
+
+    let (tx, rx) = channel();
+    let tx = Arc::new(Mutex::new(tx));
+
+    let props = MutProps::new(Arc::new(door), tx);
+    let door = actor_system.actor_of(props, "door".to_owned()).unwrap();
+
+    door.tell_to(door.clone(), ());
+    actor_system.run_until_idle();
+    assert_eq!(Ok((DoorState::Closed, DoorState::Open, 0)), rx.try_recv());
+
+    // The door stays open until the virtual clock reaches the timeout.
+    actor_system.advance_time(Duration::from_millis(49));
+    assert!(rx.try_recv().is_err());
+    actor_system.advance_time(Duration::from_millis(1));
+    assert_eq!(Ok((DoorState::Open, DoorState::Closed, 0)), rx.try_recv());
+
+    actor_system.shutdown();
+}
+
+#[test]
+fn fsm_termination_is_only_reported_on_stop() {
+    let actor_system = ActorSystem::new("test".to_owned());
+    actor_system.spawn_threads(1);
+
+    let (tx, rx) = channel();
+    let tx = Arc::new(Mutex::new(tx));
+
+    // 0 makes the handler panic, so that the actor is restarted, 1 stops it.
+    let creator = |sender: Arc<Mutex<Sender<DoorState>>>| {
+        FsmActor::new(DoorState::Closed, ())
+            .when(DoorState::Closed, |event, _data, _context| {
+                match event {
+                    FsmEvent::Message(message) => {
+                        match *Box::<Any>::downcast::<u32>(message).unwrap() {
+                            0 => panic!("The door is stuck"),
+                            _ => stop(),
+                        }
+                    }
+                    FsmEvent::StateTimeout => stay(),
+                }
+            })
+            .on_termination(move |state, _data| {
+                let _ = sender.lock().unwrap().send(state.clone());
+            })
+    };
+    let props = MutProps::new(Arc::new(creator), tx);
+    let door = actor_system.actor_of(props, "door".to_owned()).unwrap();
+    door.tell_to(door.clone(), 0u32);
+    assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+    door.tell_to(door.clone(), 1u32);
+    assert_eq!(Ok(DoorState::Closed), rx.recv_timeout(Duration::from_secs(1)));
+
+    actor_system.shutdown();
+}
+
+#[test]
+fn fsm_messages_without_handler_are_unhandled() {
+    let actor_system = ActorSystem::new("test".to_owned());
+    let probe = TestProbe::new();
+    let subscriber = TestProbe::new();
+    actor_system.event_stream().subscribe::<UnhandledMessage>(subscriber.actor_ref());
+
+    // The open state has no handler.
+    let creator = |_| {
+        FsmActor::new(DoorState::Closed, ())
+            .when(DoorState::Closed, |_event, _data, _context| goto(DoorState::Open))
+    };
+    let props = MutProps::new(Arc::new(creator), ());
+    let door = actor_system.actor_of(props, "door".to_owned()).unwrap();
+    probe.send(door.clone(), 1u32);
+    probe.send(door.clone(), 2u32);
+    let event = subscriber.expect_msg::<UnhandledMessage>(Duration::from_secs(1));
+    assert_eq!("u32", event.type_name);
+    assert_eq!(door, event.recipient);
+
+    actor_system.shutdown();
+}
+
+// This actor answers with what it is sent.
+#[derive(Default)]
+struct Echo;
+
+impl Actor for Echo {
+    fn receive(&self, message: Box<Any>, context: ActorCell) {
+        match Box::<Any>::downcast::<u32>(message) {
+            Ok(message) => context.tell(context.sender(), *message),
+            Err(message) => self.unhandled(message, context),
+        }
+    }
+}
+
+impl Echo {
+    fn new(_dummy: ()) -> Echo {
+        Echo
+    }
+}
+
+#[test]
+fn test_probe_expectations() {
+    let actor_system = ActorSystem::new("test".to_owned());
+    actor_system.spawn_threads(1);
+
+    let props = Props::new(Arc::new(Echo::new), ());
+    let echo = actor_system.actor_of(props, "echo".to_owned()).unwrap();
+    let probe = TestProbe::new();
+    probe.watch(&echo);
+
+    probe.send(echo.clone(), 42u32);
+    assert_eq!(42u32, probe.expect_msg::<u32>(Duration::from_secs(1)));
+    assert_eq!(Some(echo.clone()), probe.last_sender());
+    probe.expect_no_msg(Duration::from_millis(50));
+
+    echo.receive(InnerMessage::Control(ControlMessage::PoisonPill), probe.actor_ref());
+    probe.expect_terminated(&echo, Duration::from_secs(1));
+
+    actor_system.shutdown();
+}
+
+#[test]
+fn test_probe_auto_pilot() {
+    let probe = TestProbe::new();
+    probe.set_auto_pilot(|sender, message, probe| {
+        if let Some(n) = message.downcast_ref::<u32>() {
+            probe.tell_to(sender.clone(), n + 1);
+            // The auto pilot can send messages to its own probe.
+            probe.tell_to(probe.clone(), n.to_string());
+        }
+        true
+    });
+
+    let res = probe.actor_ref().ask(1u32).await().unwrap();
+    assert_eq!(2u32, *Box::<Any>::downcast::<u32>(res).unwrap());
+    assert_eq!("1".to_owned(), probe.expect_msg::<String>(Duration::from_secs(1)));
+    assert_eq!(1u32, probe.expect_msg::<u32>(Duration::from_secs(1)));
+}
+
+#[derive(Clone, Copy)]
+enum RegisterMessage {
+    Get,
+    Set(u32),
+}
+
+// A register with a racy read-modify-write protocol, it counts the writes to check the value.
+struct Register {
+    state: Arc<Mutex<(u32, u32)>>,
+}
+
+impl Actor for Register {
+    fn receive(&self, message: Box<Any>, context: ActorCell) {
+        if let Ok(message) = Box::<Any>::downcast::<RegisterMessage>(message) {
+            let mut state = self.state.lock().unwrap();
+            match *message {
+                RegisterMessage::Get => context.tell(context.sender(), state.0),
+                RegisterMessage::Set(value) => *state = (value, state.1 + 1),
+            }
+        }
+    }
+}
+
+// Increments the register it is sent.
+struct Incrementer;
+
+impl Actor for Incrementer {
+    fn receive(&self, message: Box<Any>, context: ActorCell) {
+        let message = match Box::<Any>::downcast::<ActorRef>(message) {
+            Ok(register) => {
+                context.tell(*register, RegisterMessage::Get);
+                return;
+            }
+            Err(message) => message,
+        };
+        if let Ok(value) = Box::<Any>::downcast::<u32>(message) {
+            context.tell(context.sender(), RegisterMessage::Set(*value + 1));
+        }
+    }
+}
+
+#[test]
+fn explorer_finds_lost_update() {
+    let setup = |actor_system: &ActorSystem| {
+        let state = Arc::new(Mutex::new((0, 0)));
+        let register = actor_system.actor_of(Props::new(Arc::new(|state| Register { state: state }),
+                                                        state.clone()),
+                                             "register".to_owned())
+                                   .unwrap();
+        let incrementers: Vec<ActorRef> = ["a", "b"]
+            .iter()
+            .map(|name| {
+                actor_system.actor_of(Props::new(Arc::new(|_| Incrementer), ()), name.to_string())
+                            .unwrap()
+            })
+            .collect();
+        for incrementer in incrementers {
+            incrementer.tell_to(incrementer.clone(), register.clone());
+        }
+        state
+    };
+    let invariant = |_actor_system: &ActorSystem, state: &Arc<Mutex<(u32, u32)>>| {
+        let (value, writes) = *state.lock().unwrap();
+        if value == writes {
+            Ok(())
+        } else {
+            Err(format!("value is {} after {} writes", value, writes))
+        }
+    };
+
+    let explorer = Explorer::new(20);
+    let violation = explorer.run(setup, invariant).unwrap_err();
+    // Both reads must happen before the second write.
+    assert!(violation.trace.iter().filter(|path| *path.logical_path() == "/user/register").count() >= 4);
+    let replayed = explorer.replay(violation.seed, setup, invariant).unwrap_err();
+    assert_eq!(violation.trace, replayed.trace);
+}
+
+#[test]
+fn system_snapshot() {
+    let actor_system = ActorSystem::new_deterministic("test".to_owned(), 0);
+
+    let props = Props::new(Arc::new(Dummy::new), ());
+    let dummy = actor_system.actor_of(props.clone(), "dummy".to_owned()).unwrap();
+    actor_system.actor_of(props, "other".to_owned()).unwrap();
+    actor_system.run_until_idle();
+    dummy.tell_to(dummy.clone(), ());
+    dummy.tell_to(dummy.clone(), ());
+
+    let snapshot = actor_system.snapshot();
+    assert_eq!(2, snapshot.user.as_ref().unwrap().child_count());
+    let dummy_snapshot = snapshot.find("/user/dummy").unwrap();
+    assert_eq!(ActorState::Running, dummy_snapshot.state);
+    assert_eq!(2, dummy_snapshot.mailbox_len);
+    assert_eq!(0, dummy_snapshot.restart_count);
+    assert!(snapshot.find("/system/dead_letters").is_some());
+
+    assert!(snapshot.to_text().contains("dummy [Running] mailbox: 2, system mailbox: 0, restarts: 0, children: 0"));
+    assert!(snapshot.to_dot().contains("\"/user\" -> \"/user/dummy\";"));
+
+    actor_system.shutdown();
+}
+
+// This actor creates a top level echo actor with the name it is sent, and answers with its ref.
+struct Spawner;
+
+impl Actor for Spawner {
+    fn receive(&self, message: Box<Any>, context: ActorCell) {
+        if let Ok(name) = Box::<Any>::downcast::<String>(message) {
+            let props = Props::new(Arc::new(Echo::new), ());
+            let echo = context.system().actor_of(props, *name).unwrap();
+            context.tell(context.sender(), echo);
+        }
+    }
+}
+
+#[test]
+fn actor_of_from_inside_an_actor() {
+    let actor_system = ActorSystem::new("test".to_owned());
+    actor_system.spawn_threads(1);
+
+    let spawner = actor_system.actor_of(Props::new(Arc::new(|_| Spawner), ()), "spawner".to_owned())
+                              .unwrap();
+    let probe = TestProbe::new();
+    // With a single consumer thread, this would deadlock if the creation waited for /user.
+    probe.send(spawner, "echo".to_owned());
+    let echo = probe.expect_msg::<ActorRef>(Duration::from_secs(1));
+    assert_eq!("/user/echo", *echo.path().logical_path());
+    probe.send(echo, 7u32);
+    assert_eq!(7, probe.expect_msg::<u32>(Duration::from_secs(1)));
+
+    actor_system.shutdown();
+}
+
+#[test]
+fn metrics() {
+    let actor_system = ActorSystem::new("test".to_owned());
+    actor_system.spawn_threads(1);
+
+    let props = Props::new(Arc::new(Echo::new), ());
+    let echo = actor_system.actor_of(props, "echo".to_owned()).unwrap();
+    let probe = TestProbe::new();
+    for i in 0..3u32 {
+        probe.send(echo.clone(), i);
+        assert_eq!(i, probe.expect_msg::<u32>(Duration::from_secs(1)));
+    }
+    // The reply is sent before the end of the handling of the message.
+    std::thread::sleep(Duration::from_millis(10));
+
+    let metrics = actor_system.metrics();
+    let echo_metrics = metrics.actor("/user/echo").unwrap();
+    assert_eq!(3, echo_metrics.messages);
+    assert_eq!(3, echo_metrics.processing_time.count());
+    assert_eq!(3, echo_metrics.queue_time.count());
+    assert!(metrics.threads().iter().any(|thread| thread.is_running() && thread.messages > 0));
+
+    let exposition = metrics.to_prometheus();
+    assert!(exposition.contains("robots_actor_messages_total{path=\"/user/echo\"} 3"));
+    assert!(exposition.contains("robots_actor_processing_seconds_count{path=\"/user/echo\"} 3"));
+    assert!(exposition.contains("robots_actor_processing_seconds_bucket{path=\"/user/echo\",le=\"+Inf\"} 3"));
+
+    actor_system.shutdown();
+}
+
+// This actor blocks its consumer thread for the number of milliseconds it is sent.
+struct Sleeper;
+
+impl Actor for Sleeper {
+    fn receive(&self, message: Box<Any>, _context: ActorCell) {
+        if let Ok(millis) = Box::<Any>::downcast::<u64>(message) {
+            std::thread::sleep(Duration::from_millis(*millis));
+        }
+    }
+}
+
+#[test]
+fn watchdog_replaces_stuck_thread() {
+    let actor_system = ActorSystem::new("test".to_owned());
+    actor_system.spawn_threads(1);
+    actor_system.start_watchdog(Duration::from_millis(50), true);
+    let probe = TestProbe::new();
+    actor_system.watchdog().subscribe(probe.actor_ref());
+
+    let sleeper = actor_system.actor_of(Props::new(Arc::new(|_| Sleeper), ()), "sleeper".to_owned())
+                              .unwrap();
+    let echo = actor_system.actor_of(Props::new(Arc::new(Echo::new), ()), "echo".to_owned()).unwrap();
+    probe.send(sleeper, 500u64);
+
+    let report = probe.expect_msg::<SlowReceive>(Duration::from_millis(300));
+    assert_eq!("/user/sleeper", *report.path.logical_path());
+    assert_eq!("u64", report.message_type);
+    // The only consumer thread is stuck, the echo answers thanks to the replacement thread.
+    probe.send(echo, 1u32);
+    assert_eq!(1, probe.expect_msg::<u32>(Duration::from_millis(100)));
+    assert_eq!(1, actor_system.watchdog().replacements());
+
+    std::thread::sleep(Duration::from_millis(500));
+    assert_eq!(0, actor_system.watchdog().replacements());
+
+    actor_system.shutdown();
+}
+
+// This actor asks the actor it is sent for the echo of 3 and waits for the answer.
+struct Asker;
+
+impl Actor for Asker {
+    fn receive(&self, message: Box<Any>, context: ActorCell) {
+        if let Ok(target) = Box::<Any>::downcast::<ActorRef>(message) {
+            let answer = target.ask(3u32).await().unwrap();
+            context.tell(context.sender(), *Box::<Any>::downcast::<u32>(answer).unwrap());
+        }
+    }
+}
+
+#[test]
+fn blocking_ask_is_compensated() {
+    let actor_system = ActorSystem::new("test".to_owned());
+    actor_system.spawn_threads(1);
+
+    let asker = actor_system.actor_of(Props::new(Arc::new(|_| Asker), ()), "asker".to_owned()).unwrap();
+    let echo = actor_system.actor_of(Props::new(Arc::new(Echo::new), ()), "echo".to_owned()).unwrap();
+    let probe = TestProbe::new();
+    // With a single consumer thread, the echo could not answer without the compensating thread.
+    probe.send(asker.clone(), echo.clone());
+    assert_eq!(3, probe.expect_msg::<u32>(Duration::from_secs(1)));
+    assert_eq!(0, actor_system.compensating_threads());
+
+    actor_system.set_blocking_ask_policy(BlockingAskPolicy::FailFast);
+    probe.send(asker, echo);
+    probe.expect_no_msg(Duration::from_millis(100));
+
+    actor_system.shutdown();
+}
+
+// This actor asks the actor it is sent for the echo of 5 without blocking, and sends the outcome to
+// the ref it was created with.
+struct Requester {
+    report_to: ActorRef,
+}
+
+impl Actor for Requester {
+    fn receive(&self, message: Box<Any>, context: ActorCell) {
+        let message = match Box::<Any>::downcast::<ActorRef>(message) {
+            Ok(target) => {
+                context.ask_with(*target,
+                                 5u32,
+                                 Duration::from_millis(100),
+                                 |reply: Result<Box<Any>, AskTimeout>| match reply {
+                                     Ok(answer) => format!("{}", Box::<Any>::downcast::<u32>(answer).unwrap()),
+                                     Err(_) => "timeout".to_owned(),
+                                 });
+                return;
+            }
+            Err(message) => message,
+        };
+        if let Ok(outcome) = Box::<Any>::downcast::<String>(message) {
+            context.tell(self.report_to.clone(), *outcome);
+        }
+    }
+}
+
+#[test]
+fn ask_with() {
+    let actor_system = ActorSystem::new("test".to_owned());
+    actor_system.spawn_threads(1);
+
+    let probe = TestProbe::new();
+    let requester = actor_system.actor_of(Props::new(Arc::new(|report_to| Requester { report_to: report_to }),
+                                                     probe.actor_ref()),
+                                          "requester".to_owned())
+                                .unwrap();
+
+    // The probe is asked by a temporary actor and replies.
+    let target = TestProbe::new();
+    probe.send(requester.clone(), target.actor_ref());
+    assert_eq!(5, target.expect_msg::<u32>(Duration::from_secs(1)));
+    assert!(target.last_sender().unwrap().path().logical_path().starts_with("/temp/$"));
+    target.reply(6u32);
+    assert_eq!("6", probe.expect_msg::<String>(Duration::from_secs(1)));
+
+    // A dummy never replies.
+    let dummy = actor_system.actor_of(Props::new(Arc::new(Dummy::new), ()), "dummy".to_owned()).unwrap();
+    probe.send(requester, dummy);
+    assert_eq!("timeout", probe.expect_msg::<String>(Duration::from_secs(1)));
+
+    // The temporary actors stopped themselves.
+    std::thread::sleep(Duration::from_millis(50));
+    assert_eq!(0, actor_system.snapshot().temp.unwrap().child_count());
+
+    actor_system.shutdown();
+}
+
+#[test]
+fn pipe_futures_to_actors() {
+    let actor_system = ActorSystem::new("test".to_owned());
+    actor_system.spawn_threads(1);
+    let probe = TestProbe::new();
+    let echo = actor_system.actor_of(Props::new(Arc::new(Echo::new), ()), "echo".to_owned()).unwrap();
+
+    // The reply to an ask is received as if it had been sent to the probe.
+    pipe_to(echo.ask(4u32), probe.actor_ref(), echo.clone());
+    assert_eq!(4, probe.expect_msg::<u32>(Duration::from_secs(1)));
+    assert_eq!(echo, probe.last_sender().unwrap());
+
+    let (complete, future) = Future::<String, String>::pair();
+    pipe_to(future, probe.actor_ref(), echo.clone());
+    probe.expect_no_msg(Duration::from_millis(50));
+    complete.complete("done".to_owned());
+    assert_eq!("done", probe.expect_msg::<String>(Duration::from_secs(1)));
+
+    pipe_to(Future::<String, String>::error("failed".to_owned()), probe.actor_ref(), echo.clone());
+    assert_eq!(Status::Failure("failed".to_owned()),
+               probe.expect_msg::<Status<String>>(Duration::from_secs(1)));
+
+    actor_system.shutdown();
+}
+
+// This actor pipes the reply of the actor it is sent for the echo of 5 to the sender.
+struct Piper;
+
+impl Actor for Piper {
+    fn receive(&self, message: Box<Any>, context: ActorCell) {
+        if let Ok(target) = Box::<Any>::downcast::<ActorRef>(message) {
+            pipe_to(target.ask(5u32), context.sender(), context.actor_ref());
+        }
+    }
+}
+
+#[test]
+fn piping_an_ask_does_not_block() {
+    let actor_system = ActorSystem::new("test".to_owned());
+    actor_system.spawn_threads(1);
+    // Piping never waits for the reply, so even failing fast on blocking asks lets it through.
+    actor_system.set_blocking_ask_policy(BlockingAskPolicy::FailFast);
+    let probe = TestProbe::new();
+    let piper = actor_system.actor_of(Props::new(Arc::new(|_| Piper), ()), "piper".to_owned()).unwrap();
+    let echo = actor_system.actor_of(Props::new(Arc::new(Echo::new), ()), "echo".to_owned()).unwrap();
+
+    probe.send(piper.clone(), echo);
+    assert_eq!(5, probe.expect_msg::<u32>(Duration::from_secs(1)));
+    assert_eq!(piper, probe.last_sender().unwrap());
+
+    actor_system.shutdown();
+}
+
+// Future asking the target and sending the doubled reply to the original sender.
+struct DoubleReply {
+    reply: EventualFuture<Box<Any + Send>, &'static str>,
+    sender: ActorRef,
+    context: ActorCell,
+}
+
+impl StdFuture for DoubleReply {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        match Pin::new(&mut self.reply).poll(cx) {
+            Poll::Ready(Ok(reply)) => {
+                let n = *Box::<Any>::downcast::<u32>(reply).unwrap();
+                self.context.tell(self.sender.clone(), n * 2);
+                Poll::Ready(())
+            }
+            Poll::Ready(Err(_)) => Poll::Ready(()),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+struct Doubler {
+    target: ActorRef,
+}
+
+impl AsyncActor for Doubler {
+    fn receive(&self, message: Box<Any + Send>, context: ActorCell) -> BoxFuture {
+        let n = *Box::<Any>::downcast::<u32>(message).unwrap();
+        Box::pin(DoubleReply {
+            reply: self.target.ask_async(n),
+            sender: context.sender(),
+            context: context,
+        })
+    }
+}
+
+#[test]
+fn async_actor_handles_messages_in_order() {
+    let actor_system = ActorSystem::new("test".to_owned());
+    actor_system.spawn_threads(1);
+    let target = TestProbe::new();
+    let probe = TestProbe::new();
+    let props = AsyncProps::new(Arc::new(|target| Doubler { target: target }), target.actor_ref());
+    let doubler = actor_system.actor_of(props, "doubler".to_owned()).unwrap();
+
+    probe.send(doubler.clone(), 1u32);
+    probe.send(doubler.clone(), 2u32);
+    assert_eq!(1, target.expect_msg::<u32>(Duration::from_secs(1)));
+    // The second message waits for the future of the first one, without blocking the only
+    // consumer thread.
+    target.expect_no_msg(Duration::from_millis(50));
+    let echo = actor_system.actor_of(Props::new(Arc::new(Echo::new), ()), "echo".to_owned()).unwrap();
+    assert_eq!(3, *Box::<Any>::downcast::<u32>(echo.ask(3u32).await().unwrap()).unwrap());
+
+    target.reply(10u32);
+    assert_eq!(20, probe.expect_msg::<u32>(Duration::from_secs(1)));
+    assert_eq!(2, target.expect_msg::<u32>(Duration::from_secs(1)));
+    target.reply(21u32);
+    assert_eq!(42, probe.expect_msg::<u32>(Duration::from_secs(1)));
+
+    actor_system.shutdown();
+}
+
+#[derive(Clone)]
+struct GetBalance(ReplyTo<u64>);
+
+struct Account;
+
+impl Actor for Account {
+    fn receive(&self, message: Box<Any>, context: ActorCell) {
+        if let Ok(message) = Box::<Any>::downcast::<GetBalance>(message) {
+            message.0.tell(100, context.actor_ref());
+        }
+    }
+}
+
+#[derive(Clone)]
+enum AuditorMessage {
+    Audit,
+    // Audits with an adapter which panics.
+    FaultyAudit,
+    Balance(u64),
+}
+
+struct Auditor {
+    account: ActorRef,
+    probe: ActorRef,
+}
+
+impl Actor for Auditor {
+    fn receive(&self, message: Box<Any>, context: ActorCell) {
+        if let Ok(message) = Box::<Any>::downcast::<AuditorMessage>(message) {
+            match *message {
+                AuditorMessage::Audit => {
+                    let reply_to = context.message_adapter(Arc::new(AuditorMessage::Balance));
+                    context.tell(self.account.clone(), GetBalance(reply_to));
+                }
+                AuditorMessage::FaultyAudit => {
+                    let reply_to = context.message_adapter(Arc::new(|_: u64| -> AuditorMessage {
+                        panic!("Faulty adapter")
+                    }));
+                    context.tell(self.account.clone(), GetBalance(reply_to));
+                }
+                AuditorMessage::Balance(balance) => context.tell(self.probe.clone(), balance),
+            }
+        }
+    }
+}
+
+#[test]
+fn typed_replies() {
+    let actor_system = ActorSystem::new("test".to_owned());
+    let probe = TestProbe::new();
+    let account = actor_system.actor_of(Props::new(Arc::new(|_| Account), ()), "account".to_owned())
+                              .unwrap();
+
+    assert_eq!(100, account.ask_typed(GetBalance).await().unwrap());
+
+    let args = (account.clone(), probe.actor_ref());
+    let props = Props::new(Arc::new(|(account, probe)| Auditor { account: account, probe: probe }), args);
+    let auditor = actor_system.actor_of(props, "auditor".to_owned()).unwrap();
+    probe.send(auditor.clone(), AuditorMessage::Audit);
+    assert_eq!(100, probe.expect_msg::<u64>(Duration::from_secs(1)));
+
+    // The adapter is run by the auditor, which fails instead of the account.
+    probe.send(auditor.clone(), AuditorMessage::FaultyAudit);
+    probe.send(auditor.clone(), AuditorMessage::Audit);
+    assert_eq!(100, probe.expect_msg::<u64>(Duration::from_secs(1)));
+    assert_eq!(1, auditor.snapshot().unwrap().restart_count);
+    assert_eq!(0, account.snapshot().unwrap().restart_count);
+
+    actor_system.shutdown();
+}
+
+// This actor forwards what it is sent to the actor it proxies.
+struct Proxy {
+    target: ActorRef,
+}
+
+impl Actor for Proxy {
+    fn receive(&self, message: Box<Any>, context: ActorCell) {
+        if let Ok(message) = Box::<Any>::downcast::<u32>(message) {
+            context.forward(self.target.clone(), *message);
+        }
+    }
+}
+
+#[test]
+fn forward_and_explicit_senders() {
+    let actor_system = ActorSystem::new("test".to_owned());
+    let probe = TestProbe::new();
+    let echo = actor_system.actor_of(Props::new(Arc::new(Echo::new), ()), "echo".to_owned()).unwrap();
+    let props = Props::new(Arc::new(|target| Proxy { target: target }), echo.clone());
+    let proxy = actor_system.actor_of(props, "proxy".to_owned()).unwrap();
+
+    // The echo replies to the probe, not to the proxy.
+    probe.send(proxy, 1u32);
+    assert_eq!(1, probe.expect_msg::<u32>(Duration::from_secs(1)));
+    assert_eq!(echo, probe.last_sender().unwrap());
+
+    actor_system.tell_with_sender(echo.clone(), 2u32, probe.actor_ref());
+    assert_eq!(2, probe.expect_msg::<u32>(Duration::from_secs(1)));
+
+    // The reply goes to the dead letters.
+    actor_system.tell_no_sender(echo, 3u32);
+    let dead_letters = actor_system.dead_letters().path();
+    let metrics = actor_system.metrics();
+    let start = std::time::Instant::now();
+    while metrics.actor(dead_letters.logical_path()).map(|m| m.messages) != Some(1) {
+        assert!(start.elapsed() < Duration::from_secs(1));
+        std::thread::sleep(Duration::from_millis(1));
+    }
+
+    actor_system.shutdown();
+}
+
+#[test]
+fn inbox() {
+    let actor_system = ActorSystem::new("test".to_owned());
+    let inbox = actor_system.inbox();
+    let echo = actor_system.actor_of(Props::new(Arc::new(Echo::new), ()), "echo".to_owned()).unwrap();
+    // The inbox is registered under /system, so its path can be resolved until it is dropped.
+    assert_eq!("/system/$a", *inbox.actor_ref().path().logical_path());
+    let resolver = actor_system.actor_of(Props::new(Arc::new(Resolver::new), ()), "resolver".to_owned())
+                               .unwrap();
+    let dropped = actor_system.inbox();
+    std::thread::sleep(Duration::from_millis(50));
+    let resolved = resolver.ask("/system/$a".to_owned()).await().unwrap();
+    assert_eq!(Some(inbox.actor_ref()), *Box::<Any>::downcast::<Option<ActorRef>>(resolved).unwrap());
+    drop(dropped);
+    std::thread::sleep(Duration::from_millis(50));
+    let resolved = resolver.ask("/system/$b".to_owned()).await().unwrap();
+    assert_eq!(None, *Box::<Any>::downcast::<Option<ActorRef>>(resolved).unwrap());
+
+    inbox.send(echo.clone(), 1u32);
+    let reply = inbox.receive(Duration::from_secs(1)).unwrap();
+    assert_eq!(1, *Box::<Any>::downcast::<u32>(reply).unwrap());
+    assert_eq!(echo, inbox.last_sender().unwrap());
+    assert!(inbox.receive(Duration::from_millis(10)).is_none());
+
+    // The messages which are not selected stay in the inbox.
+    inbox.send(echo.clone(), 2u32);
+    inbox.send(echo.clone(), 3u32);
+    let reply = inbox.select(Duration::from_secs(1), |message| message.downcast_ref::<u32>() == Some(&3))
+                     .unwrap();
+    assert_eq!(3, *Box::<Any>::downcast::<u32>(reply).unwrap());
+    let reply = inbox.receive(Duration::from_secs(1)).unwrap();
+    assert_eq!(2, *Box::<Any>::downcast::<u32>(reply).unwrap());
+
+    inbox.watch(&echo);
+    echo.receive(InnerMessage::Control(ControlMessage::PoisonPill), inbox.actor_ref());
+    let notice = inbox.receive(Duration::from_secs(1)).unwrap();
+    match *Box::<Any>::downcast::<ControlMessage>(notice).unwrap() {
+        ControlMessage::Terminated(ref terminated) => assert_eq!(echo, *terminated),
+        _ => panic!("The inbox did not receive the termination of the echo actor."),
+    }
+
+    actor_system.shutdown();
+}
+
+#[test]
+fn unhandled_messages_are_published() {
+    let actor_system = ActorSystem::new("test".to_owned());
+    let probe = TestProbe::new();
+    let subscriber = TestProbe::new();
+    actor_system.event_stream().subscribe::<UnhandledMessage>(subscriber.actor_ref());
+    let echo = actor_system.actor_of(Props::new(Arc::new(Echo::new), ()), "echo".to_owned()).unwrap();
+
+    probe.send(echo.clone(), "hello".to_owned());
+    let event = subscriber.expect_msg::<UnhandledMessage>(Duration::from_secs(1));
+    assert!(event.type_name.ends_with("String"));
+    assert_eq!(probe.actor_ref(), event.sender);
+    assert_eq!(echo, event.recipient);
+
+    actor_system.event_stream().unsubscribe::<UnhandledMessage>(&subscriber.actor_ref());
+    probe.send(echo, "hello".to_owned());
+    subscriber.expect_no_msg(Duration::from_millis(50));
+
+    actor_system.shutdown();
+}
+
+// This actor does not handle the message it gives itself when it starts.
+struct EarlyUnhandled;
+
+impl Actor for EarlyUnhandled {
+    fn pre_start(&self, context: ActorCell) {
+        self.unhandled(Box::new(1u32), context);
+    }
+
+    fn receive(&self, _message: Box<Any>, _context: ActorCell) {}
+}
+
+#[test]
+fn unhandled_outside_of_a_message() {
+    let actor_system = ActorSystem::new("test".to_owned());
+    let subscriber = TestProbe::new();
+    actor_system.event_stream().subscribe::<UnhandledMessage>(subscriber.actor_ref());
+
+    // There is no sender before the first message, the dead letters are given instead.
+    let actor = actor_system.actor_of(Props::new(Arc::new(|_| EarlyUnhandled), ()), "early".to_owned())
+                            .unwrap();
+    let event = subscriber.expect_msg::<UnhandledMessage>(Duration::from_secs(1));
+    assert_eq!(actor_system.dead_letters(), event.sender);
+    assert_eq!(actor, event.recipient);
+
+    actor_system.shutdown();
+}
+
+#[derive(Clone, Dispatch)]
+enum CounterMessage {
+    #[dispatch_from]
+    Add(u32),
+    Get,
+}
+
+struct Counter {
+    count: Mutex<u32>,
+}
+
+impl Actor for Counter {
+    fn receive(&self, message: Box<Any>, context: ActorCell) {
+        match CounterMessage::dispatch(message) {
+            Ok(CounterMessage::Add(n)) => *self.count.lock().unwrap() += n,
+            Ok(CounterMessage::Get) => context.tell(context.sender(), *self.count.lock().unwrap()),
+            Err(message) => self.unhandled(message, context),
+        }
+    }
+}
+
+#[test]
+fn derived_dispatch() {
+    let actor_system = ActorSystem::new("test".to_owned());
+    let probe = TestProbe::new();
+    let props = Props::new(Arc::new(|_| Counter { count: Mutex::new(0) }), ());
+    let counter = actor_system.actor_of(props, "counter".to_owned()).unwrap();
+
+    probe.send(counter.clone(), CounterMessage::Add(1));
+    probe.send(counter.clone(), 2u32);
+    probe.send(counter.clone(), CounterMessage::Get);
+    assert_eq!(3, probe.expect_msg::<u32>(Duration::from_secs(1)));
+
+    actor_system.shutdown();
+}
+
+// Answers the even numbers with their half, and the strings with their length.
+struct Halver;
+
+impl Actor for Halver {
+    fn receive(&self, message: Box<Any>, context: ActorCell) {
+        receive!(self, message, context, {
+            u32 as 0 => context.tell(context.sender(), "zero".to_owned()),
+            u32 as n if n % 2 == 0 => context.tell(context.sender(), n / 2),
+            String as s => {
+                context.tell(context.sender(), s.len() as u32);
+            }
+            CounterMessage as CounterMessage::Get => context.tell(context.sender(), 0u32),
+        });
+    }
+}
+
+#[test]
+fn receive_macro() {
+    let actor_system = ActorSystem::new("test".to_owned());
+    let probe = TestProbe::new();
+    let subscriber = TestProbe::new();
+    actor_system.event_stream().subscribe::<UnhandledMessage>(subscriber.actor_ref());
+    let halver = actor_system.actor_of(Props::new(Arc::new(|_| Halver), ()), "halver".to_owned())
+                             .unwrap();
+
+    probe.send(halver.clone(), 0u32);
+    assert_eq!("zero", probe.expect_msg::<String>(Duration::from_secs(1)));
+    probe.send(halver.clone(), 8u32);
+    assert_eq!(4, probe.expect_msg::<u32>(Duration::from_secs(1)));
+    probe.send(halver.clone(), "four".to_owned());
+    assert_eq!(4, probe.expect_msg::<u32>(Duration::from_secs(1)));
+    probe.send(halver.clone(), CounterMessage::Get);
+    assert_eq!(0, probe.expect_msg::<u32>(Duration::from_secs(1)));
+
+    // An odd number matches the type of an arm but none of the patterns.
+    probe.send(halver.clone(), 3u32);
+    probe.send(halver.clone(), CounterMessage::Add(1));
+    probe.expect_no_msg(Duration::from_millis(50));
+    assert_eq!(halver, subscriber.expect_msg::<UnhandledMessage>(Duration::from_secs(1)).recipient);
+    assert_eq!(halver, subscriber.expect_msg::<UnhandledMessage>(Duration::from_secs(1)).recipient);
+
+    actor_system.shutdown();
+}
+
+// Counter keeping its state in plain fields.
+struct MutCounter {
+    count: u32,
+    restarted: bool,
+}
+
+impl ActorMut for MutCounter {
+    fn receive(&mut self, message: Box<Any>, context: ActorCell) {
+        match CounterMessage::dispatch(message) {
+            Ok(CounterMessage::Add(0)) => panic!("Adding nothing"),
+            Ok(CounterMessage::Add(n)) => self.count += n,
+            Ok(CounterMessage::Get) => context.tell(context.sender(), (self.count, self.restarted)),
+            Err(message) => self.unhandled(message, context),
+        }
+    }
+
+    fn post_restart(&mut self, _context: ActorCell) {
+        self.restarted = true;
+    }
+}
+
+#[test]
+fn actor_mut() {
+    let actor_system = ActorSystem::new("test".to_owned());
+    let probe = TestProbe::new();
+    let props = MutProps::new(Arc::new(|_| MutCounter { count: 0, restarted: false }), ());
+    let counter = actor_system.actor_of(props, "counter".to_owned()).unwrap();
+
+    probe.send(counter.clone(), 1u32);
+    probe.send(counter.clone(), 2u32);
+    probe.send(counter.clone(), CounterMessage::Get);
+    assert_eq!((3, false), probe.expect_msg::<(u32, bool)>(Duration::from_secs(1)));
+
+    // The restarted actor is a new one.
+    probe.send(counter.clone(), 0u32);
+    probe.send(counter.clone(), CounterMessage::Get);
+    assert_eq!((0, true), probe.expect_msg::<(u32, bool)>(Duration::from_secs(1)));
+
+    actor_system.shutdown();
+}
+
+struct Greeting(String);
+
+impl Extension for Greeting {
+    fn create(_system: &ActorSystem) -> Greeting {
+        Greeting("Hello".to_owned())
+    }
+}
+
+// Greets with the greeting service, panics on empty names.
+struct Greeter {
+    greeting: Arc<Greeting>,
+}
+
+impl Actor for Greeter {
+    fn receive(&self, message: Box<Any>, context: ActorCell) {
+        receive!(self, message, context, {
+            String as ref name if name.is_empty() => panic!("No name"),
+            String as name => context.tell(context.sender(), format!("{} {}", self.greeting.0, name)),
+        });
+    }
+}
+
+#[test]
+fn props_from_closures() {
+    let actor_system = ActorSystem::new("test".to_owned());
+    let probe = TestProbe::new();
+
+    let echo = actor_system.actor_of(Props::<(), Echo>::default(), "echo".to_owned()).unwrap();
+    probe.send(echo, 1u32);
+    assert_eq!(1, probe.expect_msg::<u32>(Duration::from_secs(1)));
+
+    let start = 10;
+    let props = Props::from_fn(move || Counter { count: Mutex::new(start) });
+    let counter = actor_system.actor_of(props, "counter".to_owned()).unwrap();
+    probe.send(counter, CounterMessage::Get);
+    assert_eq!(10, probe.expect_msg::<u32>(Duration::from_secs(1)));
+
+    // The restarted greeter is given the service too.
+    let greeting = Arc::new(Greeting("Hello".to_owned()));
+    let props = Props::with_service(greeting, |greeting| Greeter { greeting: greeting });
+    let greeter = actor_system.actor_of(props, "greeter".to_owned()).unwrap();
+    probe.send(greeter.clone(), String::new());
+    probe.send(greeter, "world".to_owned());
+    assert_eq!("Hello world", probe.expect_msg::<String>(Duration::from_secs(1)));
+
+    // The greeting can also be an extension of the actor system.
+    let props = Props::with_extension(&actor_system, |greeting| Greeter { greeting: greeting });
+    let greeter = actor_system.actor_of(props, "registered_greeter".to_owned()).unwrap();
+    probe.send(greeter, "extensions".to_owned());
+    assert_eq!("Hello extensions", probe.expect_msg::<String>(Duration::from_secs(1)));
+
+    actor_system.shutdown();
+}
+
+static CREATED_METRICS: AtomicUsize = AtomicUsize::new(0);
+static SHUT_DOWN_EXTENSIONS: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
+
+// Extension with its own system actor.
+struct Metrics {
+    recorder: ActorRef,
+}
+
+impl Extension for Metrics {
+    fn create(system: &ActorSystem) -> Metrics {
+        CREATED_METRICS.fetch_add(1, Ordering::SeqCst);
+        let props = Props::new(Arc::new(Echo::new), ());
+        Metrics { recorder: system.system_actor_of(props, "metrics".to_owned()).unwrap() }
+    }
+
+    fn shutdown(&self) {
+        SHUT_DOWN_EXTENSIONS.lock().unwrap().push("metrics");
+    }
+}
+
+// Extension depending on the metrics one.
+struct Cluster {
+    metrics: Arc<Metrics>,
+}
+
+impl Extension for Cluster {
+    fn create(system: &ActorSystem) -> Cluster {
+        Cluster { metrics: system.register_extension::<Metrics>() }
+    }
+
+    fn shutdown(&self) {
+        SHUT_DOWN_EXTENSIONS.lock().unwrap().push("cluster");
+    }
+}
+
+#[test]
+fn extensions() {
+    let actor_system = ActorSystem::new("test".to_owned());
+    let probe = TestProbe::new();
+
+    // The metrics are registered by the cluster, and created only once.
+    assert!(actor_system.extension::<Metrics>().is_none());
+    let cluster = actor_system.register_extension::<Cluster>();
+    let metrics = actor_system.extension::<Metrics>().unwrap();
+    assert!(Arc::ptr_eq(&cluster.metrics, &metrics));
+    assert!(Arc::ptr_eq(&cluster, &actor_system.register_extension::<Cluster>()));
+    assert_eq!(1, CREATED_METRICS.load(Ordering::SeqCst));
+
+    assert_eq!("/system/metrics", *metrics.recorder.path().logical_path());
+    probe.send(metrics.recorder.clone(), 1u32);
+    assert_eq!(1, probe.expect_msg::<u32>(Duration::from_secs(1)));
+
+    // The metrics were registered first.
+    actor_system.shutdown();
+    assert_eq!(vec!["cluster", "metrics"], *SHUT_DOWN_EXTENSIONS.lock().unwrap());
+}
+
+// Extensions depending on each other.
+struct Chicken;
+struct Egg;
+
+impl Extension for Chicken {
+    fn create(system: &ActorSystem) -> Chicken {
+        system.register_extension::<Egg>();
+        Chicken
+    }
+}
+
+impl Extension for Egg {
+    fn create(system: &ActorSystem) -> Egg {
+        system.register_extension::<Chicken>();
+        Egg
+    }
+}
+
+#[test]
+fn extension_cycles_panic() {
+    let actor_system = ActorSystem::new("test".to_owned());
+
+    let cycle = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        actor_system.register_extension::<Chicken>();
+    }));
+    let message = cycle.unwrap_err().downcast::<String>().unwrap();
+    assert!(message.ends_with("Chicken -> test::Egg -> test::Chicken"), "{}", message);
+
+    // The registry can still be used.
+    assert!(actor_system.extension::<Chicken>().is_none());
+    actor_system.register_extension::<Greeting>();
+    assert!(actor_system.extension::<Greeting>().is_some());
+
+    actor_system.shutdown();
+}
+
+#[cfg(feature = "tokio")]
+struct TaskSpawner {
+    probe: ActorRef,
+}
+
+#[cfg(feature = "tokio")]
+impl Actor for TaskSpawner {
+    fn receive(&self, message: Box<Any>, context: ActorCell) {
+        if message.is::<()>() {
+            context.spawn_task(std::future::ready(7u32));
+        } else if let Ok(n) = Box::<Any>::downcast::<u32>(message) {
+            context.tell(self.probe.clone(), *n);
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn actors_on_tokio() {
+    use robots::actors::tokio_bridge;
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let actor_system = ActorSystem::with_tokio("test".to_owned(), runtime.handle().clone());
+    assert!(actor_system.is_on_tokio());
+    let probe = TestProbe::new();
+
+    let echo = actor_system.actor_of(Props::new(Arc::new(Echo::new), ()), "echo".to_owned()).unwrap();
+    let reply = runtime.block_on(echo.ask_async(3u32)).unwrap();
+    assert_eq!(3, *Box::<Any>::downcast::<u32>(reply).unwrap());
+
+    let props = Props::new(Arc::new(|probe| TaskSpawner { probe: probe }), probe.actor_ref());
+    let spawner = actor_system.actor_of(props, "spawner".to_owned()).unwrap();
+    tokio_bridge::tell(&actor_system, &spawner, ());
+    assert_eq!(7, probe.expect_msg::<u32>(Duration::from_secs(1)));
+    assert_eq!(spawner, probe.last_sender().unwrap());
+
+    actor_system.shutdown();
+}